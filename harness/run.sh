@@ -0,0 +1,15 @@
+#!/bin/sh
+# cdylibをビルドし、C言語のハーネスからload・request・unloadを呼び出します
+# savedata.txtは一時ディレクトリにコピーしたゴーストに書き込みます
+set -eu
+
+root=$(cd "$(dirname "$0")/.." && pwd)
+work=$(mktemp -d)
+trap 'rm -rf "$work"' EXIT
+
+cargo build --manifest-path "$root/Cargo.toml" --lib
+cp -R "$root/harness/ghost" "$work/ghost"
+${CC:-cc} -Wall -Wextra -o "$work/shiori" "$root/harness/shiori.c" -ldl
+
+"$work/shiori" "$root/target/debug/libweb_satori.so" "$work/ghost"
+grep -q "経過秒数" "$work/ghost/savedata.txt"
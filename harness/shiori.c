@@ -0,0 +1,105 @@
+/*
+ * ベースウェアの代わりにshiori.dll（Linuxでは.so）を読み込み、
+ * load → request → unload の順に呼び出します
+ *
+ * 使い方: shiori <ライブラリのパス> <ゴーストのディレクトリ>
+ * Windows以外ではGlobalAlloc/GlobalFreeの代わりにmalloc/freeでメモリをやりとりします
+ */
+
+#include <dlfcn.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef int (*load_fn)(void *h, long len);
+typedef int (*unload_fn)(void);
+typedef void *(*request_fn)(void *h, long *len);
+
+static int failures = 0;
+
+/* 渡すメモリはSHIORI側で解放されるので、mallocで確保したものを渡す */
+static void *global(const char *text, long len) {
+    void *h = malloc(len);
+    memcpy(h, text, len);
+    return h;
+}
+
+static void expect(int ok, const char *message) {
+    if (!ok) {
+        fprintf(stderr, "FAILED: %s\n", message);
+        failures++;
+    }
+}
+
+/* リクエストを送り、レスポンスが expected で始まるか確かめる */
+static void request(request_fn shiori_request, const char *text, const char *expected,
+                    const char *value) {
+    long len = (long)strlen(text);
+    char *response = shiori_request(global(text, len), &len);
+    expect(response != NULL, "request returned NULL");
+    if (response == NULL) {
+        return;
+    }
+
+    printf("%.*s", (int)len, response);
+    expect(len >= (long)strlen(expected) && memcmp(response, expected, strlen(expected)) == 0,
+           expected);
+    if (value != NULL) {
+        char *copy = malloc(len + 1);
+        memcpy(copy, response, len);
+        copy[len] = '\0';
+        expect(strstr(copy, value) != NULL, value);
+        free(copy);
+    }
+    /* 返されたメモリはこちらで解放する */
+    free(response);
+}
+
+int main(int argc, char **argv) {
+    if (argc != 3) {
+        fprintf(stderr, "usage: %s <library> <ghost dir>\n", argv[0]);
+        return 2;
+    }
+
+    void *library = dlopen(argv[1], RTLD_NOW);
+    if (library == NULL) {
+        fprintf(stderr, "%s\n", dlerror());
+        return 1;
+    }
+    load_fn shiori_load = (load_fn)dlsym(library, "load");
+    unload_fn shiori_unload = (unload_fn)dlsym(library, "unload");
+    request_fn shiori_request = (request_fn)dlsym(library, "request");
+    if (shiori_load == NULL || shiori_unload == NULL || shiori_request == NULL) {
+        fprintf(stderr, "load/unload/requestが見つかりません\n");
+        return 1;
+    }
+
+    long len = (long)strlen(argv[2]);
+    expect(shiori_load(global(argv[2], len), len) == 1, "load");
+
+    request(shiori_request,
+            "GET SHIORI/3.0\r\nCharset: UTF-8\r\nSender: harness\r\nSecurityLevel: local\r\n"
+            "ID: OnBoot\r\nReference0: master\r\n\r\n",
+            "SHIORI/3.0 200 OK\r\n", "Value: \\s[0]起動しました。\\e\r\n");
+    request(shiori_request,
+            "GET SHIORI/3.0\r\nCharset: UTF-8\r\nSender: harness\r\nSecurityLevel: local\r\n"
+            "ID: OnMouseDoubleClick\r\nReference0: 0\r\nReference1: 0\r\nReference2: 0\r\n"
+            "Reference3: 0\r\nReference4: Head\r\n\r\n",
+            "SHIORI/3.0 200 OK\r\n", "Value: 頭をつつかないで。\\e\r\n");
+    request(shiori_request,
+            "NOTIFY SHIORI/3.0\r\nCharset: UTF-8\r\nSender: harness\r\nID: OnSecondChange\r\n\r\n",
+            "SHIORI/3.0 204 No Content\r\n", NULL);
+    request(shiori_request, "GET SHIORI/3.0\r\nCharset: UTF-8\r\nID: OnUnknown\r\n\r\n",
+            "SHIORI/3.0 204 No Content\r\n", NULL);
+    request(shiori_request, "GET SHIORI/2.6\r\n\r\n", "SHIORI/3.0 400 Bad Request\r\n", NULL);
+
+    expect(shiori_unload() == 1, "unload");
+    dlclose(library);
+
+    if (failures > 0) {
+        fprintf(stderr, "%d failure(s)\n", failures);
+        return 1;
+    }
+    printf("ok\n");
+    return 0;
+}
@@ -1,6 +1,8 @@
-use character::Character;
+pub use character::Character;
+pub use number::Number;
 
 mod character;
+mod number;
 
 #[derive(Debug)]
 enum Mode {
@@ -0,0 +1,282 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    ops::{Add, Mul, Neg, Sub},
+    str::FromStr,
+};
+
+/// 里々の数値
+/// 整数はi64で正確に保持し、小数や範囲外になった値はf64で保持します
+#[derive(Debug, Clone, Copy)]
+pub enum Number {
+    Integer(i64), // 整数
+    Float(f64),   // 小数
+}
+
+impl Number {
+    /// 文字列を数値として解釈します
+    /// 全角の数字・符号・小数点も受け付け、前後の空白は無視します
+    pub fn parse(s: &str) -> Option<Number> {
+        let s = s.trim_matches([' ', '\t', '　']);
+        let mut chars = s.chars().peekable();
+
+        let mut normalized = String::new();
+        match chars.peek() {
+            Some('-' | '－') => {
+                normalized.push('-');
+                chars.next();
+            }
+            Some('+' | '＋') => {
+                chars.next();
+            }
+            _ => (),
+        }
+
+        let mut digits = 0;
+        let mut has_dot = false;
+        for c in chars {
+            match c {
+                '0'..='9' => normalized.push(c),
+                '０'..='９' => normalized.push(char::from(b'0' + (c as u32 - '０' as u32) as u8)),
+                '.' | '．' if !has_dot => {
+                    has_dot = true;
+                    normalized.push('.');
+                    continue;
+                }
+                _ => return None,
+            }
+            digits += 1;
+        }
+        if digits == 0 {
+            return None;
+        }
+
+        if !has_dot {
+            if let Ok(n) = normalized.parse::<i64>() {
+                return Some(Number::Integer(n));
+            }
+        }
+        normalized.parse::<f64>().ok().map(Number::Float)
+    }
+
+    /// 里々の変数値として数値に変換します
+    /// 数値として読めない文字列（空文字列を含む）は0として扱います
+    pub fn coerce(s: &str) -> Number {
+        Number::parse(s).unwrap_or(Number::Integer(0))
+    }
+
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Number::Integer(n) => n as f64,
+            Number::Float(n) => n,
+        }
+    }
+
+    pub fn is_integer(self) -> bool {
+        matches!(self, Number::Integer(_))
+    }
+
+    /// 真偽値として扱います（0以外は真）
+    pub fn is_truthy(self) -> bool {
+        self.as_f64() != 0.0
+    }
+
+    /// 割り算
+    /// 整数同士で割り切れる場合は整数、それ以外は小数になります
+    /// 0で割った場合はNoneを返します
+    pub fn checked_div(self, rhs: Number) -> Option<Number> {
+        if rhs.as_f64() == 0.0 {
+            return None;
+        }
+        Some(match (self, rhs) {
+            (Number::Integer(l), Number::Integer(r)) if l.checked_rem(r) == Some(0) => {
+                Number::Integer(l / r)
+            }
+            (l, r) => Number::Float(l.as_f64() / r.as_f64()),
+        })
+    }
+
+    /// 剰余
+    /// 0で割った場合はNoneを返します
+    pub fn checked_rem(self, rhs: Number) -> Option<Number> {
+        if rhs.as_f64() == 0.0 {
+            return None;
+        }
+        Some(match (self, rhs) {
+            (Number::Integer(l), Number::Integer(r)) => {
+                l.checked_rem(r).map_or(Number::Integer(0), Number::Integer)
+            }
+            (l, r) => Number::Float(l.as_f64() % r.as_f64()),
+        })
+    }
+}
+
+impl Add for Number {
+    type Output = Number;
+
+    fn add(self, rhs: Number) -> Number {
+        match (self, rhs) {
+            (Number::Integer(l), Number::Integer(r)) => l
+                .checked_add(r)
+                .map_or(Number::Float(l as f64 + r as f64), Number::Integer),
+            (l, r) => Number::Float(l.as_f64() + r.as_f64()),
+        }
+    }
+}
+
+impl Sub for Number {
+    type Output = Number;
+
+    fn sub(self, rhs: Number) -> Number {
+        match (self, rhs) {
+            (Number::Integer(l), Number::Integer(r)) => l
+                .checked_sub(r)
+                .map_or(Number::Float(l as f64 - r as f64), Number::Integer),
+            (l, r) => Number::Float(l.as_f64() - r.as_f64()),
+        }
+    }
+}
+
+impl Mul for Number {
+    type Output = Number;
+
+    fn mul(self, rhs: Number) -> Number {
+        match (self, rhs) {
+            (Number::Integer(l), Number::Integer(r)) => l
+                .checked_mul(r)
+                .map_or(Number::Float(l as f64 * r as f64), Number::Integer),
+            (l, r) => Number::Float(l.as_f64() * r.as_f64()),
+        }
+    }
+}
+
+impl Neg for Number {
+    type Output = Number;
+
+    fn neg(self) -> Number {
+        match self {
+            Number::Integer(n) => n
+                .checked_neg()
+                .map_or(Number::Float(-(n as f64)), Number::Integer),
+            Number::Float(n) => Number::Float(-n),
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    /// 整数はそのまま、小数は有効数字15桁で丸めてから最短の表記にします（2.0は"2"）
+    /// 桁数で丸めるので、0に近い値も0にはなりません
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Number::Integer(n) => write!(f, "{}", n),
+            Number::Float(n) if n.is_finite() => {
+                // 0.1＋0.2のような誤差が表に出ないようにする
+                let rounded = format!("{:.14e}", n).parse::<f64>().unwrap_or(*n);
+                if rounded == 0.0 {
+                    f.write_str("0")
+                } else {
+                    write!(f, "{}", rounded)
+                }
+            }
+            Number::Float(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+impl FromStr for Number {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Number::parse(s).ok_or_else(|| format!("数値ではありません: {}", s))
+    }
+}
+
+impl From<i64> for Number {
+    fn from(n: i64) -> Self {
+        Number::Integer(n)
+    }
+}
+
+impl From<f64> for Number {
+    fn from(n: f64) -> Self {
+        Number::Float(n)
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Number::Integer(l), Number::Integer(r)) => l.partial_cmp(r),
+            (l, r) => l.as_f64().partial_cmp(&r.as_f64()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        assert_eq!(Number::parse("42"), Some(Number::Integer(42)));
+        assert_eq!(Number::parse("－１２"), Some(Number::Integer(-12)));
+        assert_eq!(Number::parse(" ３．５ "), Some(Number::Float(3.5)));
+        assert_eq!(
+            Number::parse("9007199254740993"),
+            Some(Number::Integer(9007199254740993))
+        );
+        assert_eq!(
+            Number::parse("99999999999999999999"),
+            Some(Number::Float(1e20))
+        );
+        assert_eq!(Number::parse(""), None);
+        assert_eq!(Number::parse("-"), None);
+        assert_eq!(Number::parse("1.2.3"), None);
+        assert_eq!(Number::parse("十"), None);
+
+        assert_eq!(Number::coerce("１０"), Number::Integer(10));
+        assert_eq!(Number::coerce(""), Number::Integer(0));
+        assert_eq!(Number::coerce("さくら"), Number::Integer(0));
+    }
+
+    #[test]
+    fn arithmetic_test() {
+        let big = Number::Integer(16_777_217);
+        assert_eq!((big + Number::Integer(1)).to_string(), "16777218");
+        assert_eq!(
+            Number::Integer(i64::MAX) + Number::Integer(1),
+            Number::Float(i64::MAX as f64 + 1.0)
+        );
+        assert_eq!(
+            Number::Integer(6).checked_div(Number::Integer(3)),
+            Some(Number::Integer(2))
+        );
+        assert_eq!(
+            Number::Integer(5).checked_div(Number::Integer(2)),
+            Some(Number::Float(2.5))
+        );
+        assert_eq!(Number::Integer(5).checked_div(Number::Integer(0)), None);
+        assert_eq!(
+            Number::Integer(5).checked_rem(Number::Integer(3)),
+            Some(Number::Integer(2))
+        );
+        assert_eq!((Number::Float(0.5) + Number::Float(1.5)).to_string(), "2");
+        assert_eq!(Number::Float(0.1).to_string(), "0.1");
+        assert_eq!((Number::Float(0.1) + Number::Float(0.2)).to_string(), "0.3");
+        assert_eq!(Number::Float(-0.00000000001).to_string(), "-0.00000000001");
+        assert_eq!(Number::Float(1.0 / 3.0).to_string(), "0.333333333333333");
+        assert_eq!(
+            (Number::Float(0.1) * Number::Float(0.0000000001)).to_string(),
+            "0.00000000001"
+        );
+        assert_eq!(Number::Float(-0.0).to_string(), "0");
+        assert!(Number::Integer(2) == Number::Float(2.0));
+        assert!(Number::Integer(2) < Number::Float(2.5));
+    }
+}
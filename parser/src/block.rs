@@ -0,0 +1,763 @@
+//! 辞書テキストを行頭の＊・＠で始まるブロックに分割し、ブロックごとに解析します
+
+use std::{fmt, sync::Arc};
+
+use lexer::Character;
+
+use crate::{
+    ast::{self, Line, Span},
+    satori::ComparisonParser,
+    token::{
+        Content, FunctionCall, Macro, Primitives, TalkWithOtherGhost, UserSelection,
+        VariableDeclaration,
+    },
+    Lexer, Number,
+};
+
+/// ブロックの種類
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockKind {
+    Talk,      // ＊
+    WordGroup, // ＠
+}
+
+/// 辞書テキスト中の1ブロック
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block<'a> {
+    pub kind: BlockKind,
+    pub text: &'a str,
+    pub span: Span,
+}
+
+/// 解析済みのブロック
+#[derive(Debug, Clone, PartialEq)]
+pub enum Parsed {
+    Talk(ast::Talk),
+    WordGroup(ast::WordGroup),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}行目: {}", self.span.line, self.message)
+    }
+}
+
+/// テキストをブロックに分割します
+/// 最初の＊・＠より前の行は里々と同じく読み飛ばします
+pub fn split(src: &str, file: usize) -> Vec<Block<'_>> {
+    let mut blocks = vec![];
+    // ブロック種類、開始バイト位置、開始行
+    let mut current: Option<(BlockKind, usize, usize)> = None;
+    let mut offset = 0;
+
+    for (idx, line) in src.split_inclusive('\n').enumerate() {
+        let kind = match line.trim_start_matches([' ', '\t']).chars().next() {
+            Some('＊') => Some(BlockKind::Talk),
+            Some('＠') => Some(BlockKind::WordGroup),
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            if let Some((prev, start, line)) = current.take() {
+                blocks.push(block(src, prev, start, offset, line, file));
+            }
+            current = Some((kind, offset, idx + 1));
+        }
+        offset += line.len();
+    }
+
+    if let Some((prev, start, line)) = current {
+        blocks.push(block(src, prev, start, offset, line, file));
+    }
+
+    blocks
+}
+
+fn block(
+    src: &str,
+    kind: BlockKind,
+    start: usize,
+    end: usize,
+    line: usize,
+    file: usize,
+) -> Block<'_> {
+    Block {
+        kind,
+        text: &src[start..end],
+        span: Span {
+            file,
+            start,
+            end,
+            line,
+        },
+    }
+}
+
+/// テキスト全体を解析します
+/// エラーのあるブロックがあれば最初のエラーを返します
+pub fn parse(src: &str, file: usize) -> Result<ast::Satori, ParseError> {
+    strict(parse_partial(src, file))
+}
+
+/// テキスト全体をエラーから回復しながら解析します
+/// エラーのあるブロックは次の行頭の＊・＠まで読み飛ばし、そのエラーはerrorsに記録します
+pub fn parse_partial(src: &str, file: usize) -> ast::Satori {
+    collect(split(src, file).iter().map(parse_block))
+}
+
+/// ブロックの解析結果をまとめます
+pub(crate) fn collect(
+    results: impl IntoIterator<Item = Result<Parsed, ParseError>>,
+) -> ast::Satori {
+    let mut satori = ast::Satori::default();
+    for result in results {
+        match result {
+            Ok(Parsed::Talk(talk)) => satori.talk.push(talk),
+            Ok(Parsed::WordGroup(word_group)) => satori.word_group.push(word_group),
+            Err(e) => satori.errors.push(e),
+        }
+    }
+    satori
+}
+
+/// エラーが記録されていれば最初のエラーを返します
+pub(crate) fn strict(satori: ast::Satori) -> Result<ast::Satori, ParseError> {
+    match satori.errors.first() {
+        Some(e) => Err(e.clone()),
+        None => Ok(satori),
+    }
+}
+
+/// 1ブロックを解析します
+pub fn parse_block(block: &Block) -> Result<Parsed, ParseError> {
+    let mut cursor = Cursor {
+        src: block.text,
+        pos: 0,
+        base: block.span,
+        line: block.span.line,
+    };
+
+    cursor.skip_indent();
+    cursor.bump();
+    let header = cursor.read_to_eol();
+    let (name, condition) = split_fields(&header);
+    let (name, tags) =
+        split_tags(name).ok_or_else(|| cursor.error("タグの閉じカッコがありません"))?;
+    let condition = condition
+        .map(|condition| parse_condition(condition, &cursor))
+        .transpose()?;
+    cursor.bump();
+
+    let talk = block.kind == BlockKind::Talk;
+    let mut contents = vec![];
+    while !cursor.is_eof() {
+        if let Some(line) = cursor.parse_line(talk)? {
+            if talk || !line.is_empty() {
+                contents.push(line);
+            }
+        }
+        cursor.bump();
+    }
+    // ブロック末尾の空行は捨てる
+    while contents.last().is_some_and(|line: &Line| line.is_empty()) {
+        contents.pop();
+    }
+
+    if talk {
+        Ok(Parsed::Talk(ast::Talk {
+            start: ast::TalkStart::new(
+                (!name.is_empty()).then(|| name.to_string()),
+                tags,
+                condition,
+            ),
+            contents,
+            span: block.span,
+        }))
+    } else {
+        if name.is_empty() {
+            Err(cursor.error_at(block.span.line, "単語群名がありません"))?
+        }
+        Ok(Parsed::WordGroup(ast::WordGroup {
+            label: name.to_string(),
+            condition,
+            contents,
+            span: block.span,
+        }))
+    }
+}
+
+/// 見出しを名前と条件式に分割します
+/// 区切りはタブです（名前には半角スペースを含められます）
+fn split_fields(text: &str) -> (&str, Option<&str>) {
+    match text.split_once('\t') {
+        Some((name, rest)) if !rest.trim().is_empty() => (name.trim(), Some(rest.trim())),
+        Some((name, _)) => (name.trim(), None),
+        None => (text.trim(), None),
+    }
+}
+
+/// 名前とタグ（名前[タグ1、タグ2]）を分割します
+/// タグの閉じカッコがない場合はNoneを返します
+fn split_tags(text: &str) -> Option<(&str, Vec<String>)> {
+    let Some((name, rest)) = text.split_once('[') else {
+        return Some((text, vec![]));
+    };
+    let (tags, _) = rest.split_once(']')?;
+    let tags = tags
+        .split(['、', ','])
+        .map(|tag| tag.trim())
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| tag.to_string())
+        .collect();
+    Some((name.trim(), tags))
+}
+
+fn parse_condition(text: &str, cursor: &Cursor) -> Result<ast::Expression, ParseError> {
+    ComparisonParser::new()
+        .parse(Lexer::new(text))
+        .map_err(|e| cursor.error(format!("条件式を解析できません: {}, {:?}", text, e)))
+}
+
+fn is_digits(s: &str) -> bool {
+    s.chars()
+        .all(|c| c.is_ascii_digit() || ('０'..='９').contains(&c))
+}
+
+struct Cursor<'a> {
+    src: &'a str, // ブロックのテキスト
+    pos: usize,   // ブロック内のバイト位置
+    base: Span,   // ブロックの位置
+    line: usize,  // 現在の行番号
+}
+
+impl Cursor<'_> {
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.src[self.pos..].chars().nth(n)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn is_eof(&self) -> bool {
+        self.pos >= self.src.len()
+    }
+
+    fn skip_indent(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.bump();
+        }
+    }
+
+    /// 行末（改行の手前）までをそのまま読み込みます
+    fn read_to_eol(&mut self) -> String {
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            if c == '\n' {
+                break;
+            }
+            self.bump();
+            if c != '\r' {
+                text.push(c);
+            }
+        }
+        text
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        self.error_at(self.line, message)
+    }
+
+    fn error_at(&self, line: usize, message: impl Into<String>) -> ParseError {
+        let pos = self.base.start + self.pos;
+        ParseError {
+            span: Span {
+                file: self.base.file,
+                start: pos,
+                end: pos,
+                line,
+            },
+            message: message.into(),
+        }
+    }
+
+    /// 1行を解析します
+    /// コメント行の場合はNoneを返します
+    fn parse_line(&mut self, talk: bool) -> Result<Option<Line>, ParseError> {
+        self.skip_indent();
+        let mut line = vec![];
+        match self.peek() {
+            Some('＃') => {
+                self.read_to_eol();
+                return Ok(None);
+            }
+            Some('：') if talk => {
+                self.bump();
+                line.push(Content::ScopeChange("：".to_string()));
+                line.extend(self.parse_segments()?);
+            }
+            Some(c @ ('＞' | '≫' | '≧')) if talk => {
+                self.bump();
+                let text = self.read_to_eol();
+                let (label, condition) = split_fields(&text);
+                if label.is_empty() {
+                    Err(self.error("ジャンプ先がありません"))?
+                }
+                let label = label.to_string();
+                line.push(match c {
+                    '＞' => Content::Jump(label),
+                    '≫' => Content::AmbiguousSearchJump(label),
+                    _ => Content::TagAmbiguousSearchJump(label),
+                });
+                if let Some(condition) = condition {
+                    line.push(Content::Condition(parse_condition(condition, self)?));
+                }
+            }
+            Some('＄') if talk => {
+                self.bump();
+                let mut name = String::new();
+                while let Some(c) = self.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+                    self.bump();
+                    if c == '＝' || c == '\t' {
+                        break;
+                    }
+                    name.push(c);
+                }
+                let name = name.trim().to_string();
+                if name.is_empty() {
+                    Err(self.error("変数名がありません"))?
+                }
+                line.push(Content::VariableDeclaration(VariableDeclaration::Name(
+                    name,
+                )));
+                let value = self.parse_segments()?;
+                match value.as_slice() {
+                    [] => line.push(Content::VariableDeclaration(VariableDeclaration::Value(
+                        Primitives::String(String::new()),
+                    ))),
+                    [Content::Sentense(s)] => {
+                        // 表記が変わってしまう数値（全角や先頭の0など）は文字列のまま保持する
+                        let primitive = match Number::parse(s) {
+                            Some(n) if n.to_string() == *s => Primitives::Number(n),
+                            _ => Primitives::String(s.clone()),
+                        };
+                        line.push(Content::VariableDeclaration(VariableDeclaration::Value(
+                            primitive,
+                        )));
+                    }
+                    _ => line.extend(value),
+                }
+            }
+            Some('＿') if talk => {
+                self.bump();
+                let text = self.read_to_eol();
+                let (content, label) = split_fields(&text);
+                line.push(Content::UserSelections(UserSelection::Content(
+                    content.to_string(),
+                )));
+                line.push(Content::UserSelections(UserSelection::Label(
+                    label.unwrap_or(content).to_string(),
+                )));
+            }
+            Some('→') if talk => {
+                self.bump();
+                for content in self.parse_segments()? {
+                    line.push(Content::TalkWithOtherGhost(match content {
+                        Content::Macro(m) => TalkWithOtherGhost::Macro(m),
+                        Content::Sentense(s) => TalkWithOtherGhost::Sentense(s),
+                        _ => unreachable!("parse_segmentsは文とマクロのみ返す"),
+                    }));
+                }
+            }
+            _ => line.extend(self.parse_segments()?),
+        }
+        Ok(Some(line))
+    }
+
+    /// 文とマクロ展開式の並びを行末まで解析します
+    fn parse_segments(&mut self) -> Result<Vec<Content>, ParseError> {
+        let mut contents = vec![];
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            let ch = Character::new(c);
+            if c == '\n' {
+                break;
+            } else if ch.is_cr() {
+                self.bump();
+            } else if ch.is_escape() {
+                self.bump();
+                match self.peek() {
+                    // Φ + 改行は次の行へ継続
+                    Some('\n') => {
+                        self.bump();
+                    }
+                    Some('\r') if self.peek_nth(1) == Some('\n') => {
+                        self.bump();
+                        self.bump();
+                    }
+                    Some(n) if Character::new(n).is_allowed_escape_next() => {
+                        self.bump();
+                        text.push(n);
+                    }
+                    _ => Err(self.error("エスケープ文字の後に不正な文字が続いています"))?,
+                }
+            } else if ch.is_cacco() {
+                if !text.is_empty() {
+                    contents.push(Content::Sentense(std::mem::take(&mut text)));
+                }
+                contents.push(Content::Macro(self.parse_macro()?));
+            } else if ch.is_cocca() {
+                Err(self.error("閉じカッコが不正です"))?
+            } else {
+                self.bump();
+                text.push(c);
+            }
+        }
+        if !text.is_empty() {
+            contents.push(Content::Sentense(text));
+        }
+        Ok(contents)
+    }
+
+    /// （…）を解析します
+    /// カッコの中の改行と、カッコ・区切りの前後の空白は無視します
+    fn parse_macro(&mut self) -> Result<Macro, ParseError> {
+        let line = self.line;
+        self.bump();
+
+        let mut args: Vec<Vec<Content>> = vec![vec![]];
+        let mut text = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                Err(self.error_at(line, "閉じカッコがありません"))?
+            };
+            let ch = Character::new(c);
+            // 区切りで文を確定させる
+            if ch.is_cocca() || ch.is_splitter() || ch.is_cacco() {
+                let sentense = std::mem::take(&mut text);
+                let sentense = sentense.trim_matches([' ', '\t']);
+                if let Some(arg) = args.last_mut().filter(|_| !sentense.is_empty()) {
+                    arg.push(Content::Sentense(sentense.to_string()));
+                }
+            }
+
+            if ch.is_cocca() {
+                self.bump();
+                break;
+            } else if ch.is_splitter() {
+                self.bump();
+                args.push(vec![]);
+            } else if ch.is_cacco() {
+                let inner = self.parse_macro()?;
+                if let Some(arg) = args.last_mut() {
+                    arg.push(Content::Macro(inner));
+                }
+            } else if ch.is_escape() {
+                self.bump();
+                match self.peek() {
+                    Some(n) if Character::new(n).is_cr() || Character::new(n).is_lf() => {}
+                    Some(n) if Character::new(n).is_allowed_escape_next() => {
+                        self.bump();
+                        text.push(n);
+                    }
+                    _ => Err(self.error("エスケープ文字の後に不正な文字が続いています"))?,
+                }
+            } else if ch.is_cr() || ch.is_lf() {
+                self.bump();
+            } else {
+                self.bump();
+                text.push(c);
+            }
+        }
+
+        if args.len() == 1 {
+            return match args.remove(0).as_slice() {
+                [Content::Sentense(s)] if is_digits(s) => Ok(Macro::SurfaceChange(s.clone())),
+                [Content::Sentense(s)] => Ok(Macro::TalkCalling(s.clone())),
+                [Content::Macro(m)] => Ok(Macro::Macro(Arc::new(m.clone()))),
+                [] => Err(self.error_at(line, "カッコの中が空です")),
+                _ => Err(self.error_at(line, "名前に文字列と展開式を混在できません")),
+            };
+        }
+
+        let mut args = args.into_iter();
+        match args.next().as_deref() {
+            Some([Content::Sentense(name)]) => Ok(Macro::FunctionCall(FunctionCall {
+                name: name.clone(),
+                arguments: args.collect(),
+            })),
+            _ => Err(self.error_at(line, "関数名が不正です")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sentense(s: &str) -> Content {
+        Content::Sentense(s.to_string())
+    }
+
+    #[test]
+    fn split_test() {
+        let src = "前置き\n＊OnBoot\nこんにちは\n\n＠天気\n晴れ\n  ＊\nやあ";
+        let blocks = split(src, 0);
+
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0].kind, BlockKind::Talk);
+        assert_eq!(blocks[0].text, "＊OnBoot\nこんにちは\n\n");
+        assert_eq!(blocks[0].span.line, 2);
+        assert_eq!(blocks[1].kind, BlockKind::WordGroup);
+        assert_eq!(blocks[1].text, "＠天気\n晴れ\n");
+        assert_eq!(blocks[2].text, "  ＊\nやあ");
+        assert_eq!(blocks[2].span.line, 7);
+        assert_eq!(
+            &src[blocks[2].span.start..blocks[2].span.end],
+            blocks[2].text
+        );
+    }
+
+    #[test]
+    fn talk_test() {
+        let satori = parse(
+            r"
+            ＊OnBoot	（現在曜日）＝＝0
+            ：こんにちは（名前）さん。
+            （０）やあ。
+            ＃コメント
+            ＞OnClose
+
+            ",
+            0,
+        )
+        .unwrap();
+
+        let talk = &satori.talk[0];
+        assert_eq!(talk.start.name(), Some("OnBoot"));
+        assert!(talk.start.condition.is_some());
+        assert_eq!(
+            talk.contents,
+            vec![
+                vec![
+                    Content::ScopeChange("：".to_string()),
+                    sentense("こんにちは"),
+                    Content::Macro(Macro::TalkCalling("名前".to_string())),
+                    sentense("さん。"),
+                ],
+                vec![
+                    Content::Macro(Macro::SurfaceChange("０".to_string())),
+                    sentense("やあ。"),
+                ],
+                vec![Content::Jump("OnClose".to_string())],
+            ]
+        );
+    }
+
+    #[test]
+    fn header_test() {
+        let satori = parse(
+            "＊OnBoot\nやあ\n＊挨拶[朝、あいさつ]\t（時）＝＝７\nおはよう\n＊[雑談]\nねえ\n＊Only\nね\n＊Hello World\nやあ\n",
+            0,
+        )
+        .unwrap();
+
+        let starts = satori.talk.iter().map(|t| &t.start).collect::<Vec<_>>();
+        assert_eq!(starts[0].kind, ast::TalkKind::Event);
+        assert_eq!(starts[1].name(), Some("挨拶"));
+        assert_eq!(starts[1].tags, vec!["朝", "あいさつ"]);
+        assert_eq!(starts[1].kind, ast::TalkKind::Named);
+        assert!(starts[1].condition.is_some());
+        assert_eq!(starts[2].label, None);
+        assert!(starts[2].has_tag("雑談"));
+        assert_eq!(starts[2].kind, ast::TalkKind::Random);
+        assert_eq!(starts[3].kind, ast::TalkKind::Named);
+        // 半角スペースは名前の一部
+        assert_eq!(starts[4].name(), Some("Hello World"));
+        assert_eq!(starts[4].condition, None);
+
+        let error = parse("＊挨拶[朝\nおはよう\n", 0).unwrap_err();
+        assert_eq!(error.message, "タグの閉じカッコがありません");
+    }
+
+    #[test]
+    fn number_condition_test() {
+        let satori = parse("＊OnBoot\t（回数）＝＝１０\nこんにちは\n", 0).unwrap();
+
+        assert_eq!(
+            satori.talk[0].start.condition,
+            Some(ast::Expression::Comparison(ast::Comparison {
+                lhs: Box::new(ast::Expression::Term(ast::Term::Factor(
+                    ast::Factor::Expression(Box::new(ast::Expression::Term(ast::Term::Factor(
+                        ast::Factor::String("回数".to_string())
+                    ))))
+                ))),
+                op: ast::Op::Equal,
+                rhs: Box::new(ast::Expression::Term(ast::Term::Factor(
+                    ast::Factor::Number(Number::Integer(10))
+                ))),
+            }))
+        );
+    }
+
+    #[test]
+    fn random_talk_test() {
+        let satori = parse("＊\nこんにちは。\n＊\nこんばんは。\n", 0).unwrap();
+
+        assert_eq!(satori.talk.len(), 2);
+        assert_eq!(satori.talk[0].start.label, None);
+        assert_eq!(satori.talk[0].start.kind, ast::TalkKind::Random);
+        assert_eq!(
+            satori.talk[1].contents,
+            vec![vec![sentense("こんばんは。")]]
+        );
+    }
+
+    #[test]
+    fn function_call_test() {
+        let satori = parse(
+            r"
+            ＊OnBoot
+            （iflist、（現在時）、
+            ＜６、こんばんは。、
+            ＜１１、おはようございます。
+            ）
+            ",
+            0,
+        )
+        .unwrap();
+
+        assert_eq!(
+            satori.talk[0].contents,
+            vec![vec![Content::Macro(Macro::FunctionCall(FunctionCall {
+                name: "iflist".to_string(),
+                arguments: vec![
+                    vec![Content::Macro(Macro::TalkCalling("現在時".to_string()))],
+                    vec![sentense("＜６")],
+                    vec![sentense("こんばんは。")],
+                    vec![sentense("＜１１")],
+                    vec![sentense("おはようございます。")],
+                ],
+            }))]]
+        );
+
+        // 引数の中の空白はそのまま残す
+        let satori = parse("＊\n（replace、 hello world 、o、0）\n", 0).unwrap();
+        assert_eq!(
+            satori.talk[0].contents,
+            vec![vec![Content::Macro(Macro::FunctionCall(FunctionCall {
+                name: "replace".to_string(),
+                arguments: vec![
+                    vec![sentense("hello world")],
+                    vec![sentense("o")],
+                    vec![sentense("0")],
+                ],
+            }))]]
+        );
+    }
+
+    #[test]
+    fn variable_test() {
+        let satori = parse("＊\n＄回数＝10\n＄名前\tさくら（敬称）\n", 0).unwrap();
+
+        assert_eq!(
+            satori.talk[0].contents,
+            vec![
+                vec![
+                    Content::VariableDeclaration(VariableDeclaration::Name("回数".to_string())),
+                    Content::VariableDeclaration(VariableDeclaration::Value(Primitives::Number(
+                        Number::Integer(10)
+                    ))),
+                ],
+                vec![
+                    Content::VariableDeclaration(VariableDeclaration::Name("名前".to_string())),
+                    sentense("さくら"),
+                    Content::Macro(Macro::TalkCalling("敬称".to_string())),
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn word_group_test() {
+        let satori = parse("＠天気\n晴れ\n\n雨（記号）\nΦ＊曇り\n", 0).unwrap();
+
+        let word_group = &satori.word_group[0];
+        assert_eq!(word_group.label, "天気");
+        assert_eq!(
+            word_group.contents,
+            vec![
+                vec![sentense("晴れ")],
+                vec![
+                    sentense("雨"),
+                    Content::Macro(Macro::TalkCalling("記号".to_string()))
+                ],
+                vec![sentense("＊曇り")],
+            ]
+        );
+    }
+
+    #[test]
+    fn error_test() {
+        let error = parse("＊\nこんにちは\n（名前\n＊\nやあ", 0).unwrap_err();
+        assert_eq!(error.span.line, 3);
+        assert_eq!(error.to_string(), "3行目: 閉じカッコがありません");
+
+        let error = parse("＊\n\nこんにちは）\n", 0).unwrap_err();
+        assert_eq!(error.span.line, 3);
+
+        for jump in ["＞", "≫", "≧\t（a）＝＝1"] {
+            let error = parse(&format!("＊\n{}\n", jump), 0).unwrap_err();
+            assert_eq!(error.message, "ジャンプ先がありません");
+        }
+    }
+
+    #[test]
+    fn partial_test() {
+        let satori = parse_partial(
+            "＊OnBoot\nこんにちは\n（名前\n＊OnClose\nさようなら\n＠\n晴れ\n＊\nやあ）\n＠天気\n雨\n",
+            0,
+        );
+
+        assert_eq!(satori.talk.len(), 1);
+        assert_eq!(satori.talk[0].start.name(), Some("OnClose"));
+        assert_eq!(satori.word_group.len(), 1);
+        assert_eq!(satori.word_group[0].label, "天気");
+        assert_eq!(
+            satori
+                .errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "3行目: 閉じカッコがありません",
+                "6行目: 単語群名がありません",
+                "9行目: 閉じカッコが不正です",
+            ]
+        );
+        assert_eq!(
+            parse("＊OnBoot\nこんにちは\n（名前\n", 0)
+                .unwrap_err()
+                .span
+                .line,
+            3
+        );
+    }
+}
@@ -0,0 +1,375 @@
+//! ゴーストのディレクトリから辞書ファイル（dic*.txt）を読み込み、1つの辞書にまとめます
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ast::{self, Line, Span},
+    block::{self, ParseError},
+};
+
+/// 複数ファイルをまとめた辞書
+#[derive(Debug, Clone, Default)]
+pub struct Dictionary {
+    pub files: Vec<PathBuf>, // 読み込んだファイル（Span::fileの番号順）
+    pub satori: ast::Satori, // 全ファイルのトークと単語群
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(PathBuf, io::Error),
+    Encoding(PathBuf),
+    Parse(PathBuf, ParseError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            LoadError::Encoding(path) => {
+                write!(f, "{}: 文字コードを判別できません", path.display())
+            }
+            LoadError::Parse(path, e) => write!(f, "{}: {}", path.display(), e),
+        }
+    }
+}
+
+/// ファイルをまたいだ重複・衝突
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conflict {
+    // 見出しも内容も同じトークが複数ある
+    DuplicateTalk {
+        label: Option<String>,
+        first: Span,
+        second: Span,
+    },
+    // 同じ名前の単語群に同じ単語が複数ある
+    DuplicateWord {
+        group: String,
+        word: Line,
+        first: Span,
+        second: Span,
+    },
+    // 同じ名前がトークと単語群の両方で定義されている
+    TalkAndWordGroup {
+        label: String,
+        talk: Span,
+        word_group: Span,
+    },
+}
+
+impl Dictionary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// ディレクトリ内の辞書ファイルをすべて読み込みます
+    /// 読み込めないファイルやエラーのあるブロックがあれば最初のエラーを返します
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let (dictionary, errors) = Self::load_partial(dir);
+        match errors.into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(dictionary),
+        }
+    }
+
+    /// ディレクトリ内の辞書ファイルを、エラーのあるブロックやファイルを飛ばしながら読み込みます
+    pub fn load_partial(dir: impl AsRef<Path>) -> (Self, Vec<LoadError>) {
+        let dir = dir.as_ref();
+        let mut dictionary = Self::new();
+        let files = match find_files(dir) {
+            Ok(files) => files,
+            Err(e) => return (dictionary, vec![LoadError::Io(dir.to_path_buf(), e)]),
+        };
+
+        let mut errors = vec![];
+        for path in files {
+            errors.extend(dictionary.add_file_partial(path));
+        }
+        (dictionary, errors)
+    }
+
+    /// 辞書ファイルを1つ読み込んで追加します
+    /// エラーのあるブロックがあれば何も追加せずにエラーを返します
+    pub fn add_file(&mut self, path: impl AsRef<Path>) -> Result<(), LoadError> {
+        let path = path.as_ref();
+        let src = read(path)?;
+        self.add_source(path, &src)
+            .map_err(|e| LoadError::Parse(path.to_path_buf(), e))
+    }
+
+    /// 辞書ファイルを1つ、エラーのあるブロックを飛ばしながら読み込んで追加します
+    pub fn add_file_partial(&mut self, path: impl AsRef<Path>) -> Vec<LoadError> {
+        let path = path.as_ref();
+        match read(path) {
+            Ok(src) => self
+                .add_source_partial(path, &src)
+                .into_iter()
+                .map(|e| LoadError::Parse(path.to_path_buf(), e))
+                .collect(),
+            Err(e) => vec![e],
+        }
+    }
+
+    /// 読み込み済みのテキストを1ファイル分として追加します
+    /// エラーのあるブロックがあれば何も追加せずにエラーを返します
+    pub fn add_source(&mut self, path: impl AsRef<Path>, src: &str) -> Result<(), ParseError> {
+        let satori = block::strict(block::parse_partial(src, self.files.len()))?;
+        self.append(path.as_ref(), satori);
+        Ok(())
+    }
+
+    /// 読み込み済みのテキストを、エラーのあるブロックを飛ばしながら追加します
+    /// 飛ばしたブロックのエラーはsatori.errorsにも記録します
+    pub fn add_source_partial(&mut self, path: impl AsRef<Path>, src: &str) -> Vec<ParseError> {
+        let satori = block::parse_partial(src, self.files.len());
+        let errors = satori.errors.clone();
+        self.append(path.as_ref(), satori);
+        errors
+    }
+
+    fn append(&mut self, path: &Path, satori: ast::Satori) {
+        self.files.push(path.to_path_buf());
+        self.satori.talk.extend(satori.talk);
+        self.satori.word_group.extend(satori.word_group);
+        self.satori.errors.extend(satori.errors);
+    }
+
+    /// Spanが指すファイルのパスを返します
+    pub fn path(&self, span: &Span) -> Option<&Path> {
+        self.files.get(span.file).map(|path| path.as_path())
+    }
+
+    /// 重複・衝突している定義を列挙します
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts = vec![];
+        let talks = &self.satori.talk;
+        let word_groups = &self.satori.word_group;
+
+        for (i, first) in talks.iter().enumerate() {
+            for second in &talks[i + 1..] {
+                if first.start == second.start && first.contents == second.contents {
+                    conflicts.push(Conflict::DuplicateTalk {
+                        label: first.start.name().map(|name| name.to_string()),
+                        first: first.span,
+                        second: second.span,
+                    });
+                }
+            }
+        }
+
+        for (i, first) in word_groups.iter().enumerate() {
+            for second in &word_groups[i..] {
+                if first.label != second.label {
+                    continue;
+                }
+                let same = std::ptr::eq(first, second);
+                for (j, word) in first.contents.iter().enumerate() {
+                    let rest = if same {
+                        &second.contents[j + 1..]
+                    } else {
+                        &second.contents[..]
+                    };
+                    if rest.contains(word) {
+                        conflicts.push(Conflict::DuplicateWord {
+                            group: first.label.clone(),
+                            word: word.clone(),
+                            first: first.span,
+                            second: second.span,
+                        });
+                    }
+                }
+            }
+        }
+
+        for talk in talks {
+            let Some(label) = talk.start.name() else {
+                continue;
+            };
+            if let Some(word_group) = word_groups.iter().find(|w| w.label == label) {
+                conflicts.push(Conflict::TalkAndWordGroup {
+                    label: label.to_string(),
+                    talk: talk.span,
+                    word_group: word_group.span,
+                });
+            }
+        }
+
+        conflicts
+    }
+}
+
+/// 辞書ファイルかどうか判定します
+/// 里々と同じく dic*.txt を辞書として扱います
+pub fn is_dictionary_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| name.to_lowercase())
+        .is_some_and(|name| name.starts_with("dic") && name.ends_with(".txt"))
+}
+
+/// ディレクトリ以下（サブフォルダを含む）の辞書ファイルをパス順に列挙します
+/// 循環しないよう、ディレクトリへのシンボリックリンクはたどりません
+pub fn find_files(dir: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut dirs = vec![dir.as_ref().to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+            } else if is_dictionary_file(&path) && path.is_file() {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// ファイルを読み込み、文字コードを判別してテキストにします
+fn read(path: &Path) -> Result<String, LoadError> {
+    let bytes = fs::read(path).map_err(|e| LoadError::Io(path.to_path_buf(), e))?;
+    decode(&bytes).ok_or_else(|| LoadError::Encoding(path.to_path_buf()))
+}
+
+/// UTF-8（BOM付き含む）として読めなければShift_JISとして読みます
+pub fn decode(bytes: &[u8]) -> Option<String> {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    if let Ok(src) = std::str::from_utf8(bytes) {
+        return Some(src.to_string());
+    }
+    let (src, _, had_errors) = encoding_rs::SHIFT_JIS.decode(bytes);
+    (!had_errors).then(|| src.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("satori-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn load_test() {
+        let dir = temp_dir("load");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(
+            dir.join("dic01.txt"),
+            "＊OnBoot\nこんにちは\n＠天気\n晴れ\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("sub").join("dic_sub.txt"),
+            "＊OnClose\nさようなら\n",
+        )
+        .unwrap();
+        fs::write(dir.join("satori_conf.txt"), "＊無視\n").unwrap();
+        let (sjis, _, _) = encoding_rs::SHIFT_JIS.encode("＊\nやあ\n＠天気\n雨\n");
+        fs::write(dir.join("dic02.txt"), sjis).unwrap();
+        // 親ディレクトリへのリンクはたどらない
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&dir, dir.join("sub").join("loop")).unwrap();
+
+        let dictionary = Dictionary::load(&dir).unwrap();
+
+        assert_eq!(
+            dictionary.files,
+            vec![
+                dir.join("dic01.txt"),
+                dir.join("dic02.txt"),
+                dir.join("sub").join("dic_sub.txt"),
+            ]
+        );
+        assert_eq!(dictionary.satori.talk.len(), 3);
+        assert_eq!(dictionary.satori.word_group.len(), 2);
+
+        let talk = &dictionary.satori.talk[2];
+        assert_eq!(talk.start.name(), Some("OnClose"));
+        assert_eq!(
+            dictionary.path(&talk.span),
+            Some(dir.join("sub").join("dic_sub.txt").as_path())
+        );
+        assert!(dictionary.conflicts().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn conflicts_test() {
+        let mut dictionary = Dictionary::new();
+        dictionary
+            .add_source("dic01.txt", "＊OnBoot\nこんにちは\n＠天気\n晴れ\n雨\n")
+            .unwrap();
+        dictionary
+            .add_source(
+                "dic02.txt",
+                "＊OnBoot\nこんにちは\n＠天気\n雨\n＊天気\n晴れです\n",
+            )
+            .unwrap();
+
+        let conflicts = dictionary.conflicts();
+        assert_eq!(conflicts.len(), 3);
+        assert_eq!(
+            conflicts[0],
+            Conflict::DuplicateTalk {
+                label: Some("OnBoot".to_string()),
+                first: dictionary.satori.talk[0].span,
+                second: dictionary.satori.talk[1].span,
+            }
+        );
+        assert!(matches!(
+            &conflicts[1],
+            Conflict::DuplicateWord { group, first, second, .. }
+                if group == "天気" && first.file == 0 && second.file == 1
+        ));
+        assert!(matches!(
+            &conflicts[2],
+            Conflict::TalkAndWordGroup { label, talk, .. } if label == "天気" && talk.file == 1
+        ));
+    }
+
+    #[test]
+    fn parse_error_test() {
+        let mut dictionary = Dictionary::new();
+        let error = dictionary
+            .add_source("dic01.txt", "＊\n（名前\n")
+            .unwrap_err();
+
+        assert_eq!(error.span.line, 2);
+        assert!(dictionary.files.is_empty());
+    }
+
+    #[test]
+    fn load_partial_test() {
+        let dir = temp_dir("partial");
+        fs::write(
+            dir.join("dic01.txt"),
+            "＊OnBoot\n（名前\n＊OnClose\nさようなら\n",
+        )
+        .unwrap();
+        fs::write(dir.join("dic02.txt"), b"\x82\xa0\xff\xfe").unwrap();
+        fs::write(dir.join("dic03.txt"), "＊\nやあ\n").unwrap();
+
+        let (dictionary, errors) = Dictionary::load_partial(&dir);
+
+        assert_eq!(dictionary.files.len(), 2);
+        assert_eq!(dictionary.satori.talk.len(), 2);
+        assert_eq!(dictionary.satori.talk[0].start.name(), Some("OnClose"));
+        assert_eq!(errors.len(), 2);
+        assert_eq!(dictionary.satori.errors.len(), 1);
+        assert!(matches!(Dictionary::load(&dir), Err(LoadError::Parse(..))));
+        assert!(matches!(&errors[0], LoadError::Parse(path, e)
+            if path == &dir.join("dic01.txt") && e.span.line == 2));
+        assert!(matches!(&errors[1], LoadError::Encoding(path) if path == &dir.join("dic02.txt")));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,302 @@
+//! エディタ向けの差分解析
+//! 編集された範囲にかかる＊・＠ブロックだけを解析し直します
+
+use std::{fmt, ops::Range};
+
+use crate::{
+    ast::{self, Span},
+    block::{self, BlockKind, ParseError, Parsed},
+};
+
+/// テキストの編集
+/// 編集前のテキストのバイト範囲を新しい文字列で置き換えます
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+/// 適用できない編集
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditError {
+    OutOfRange(Range<usize>), // 範囲がテキストの外か、開始が終了より後
+    NotCharBoundary(usize),   // 文字の途中の位置
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::OutOfRange(range) => write!(f, "編集範囲がテキストの外です: {:?}", range),
+            EditError::NotCharBoundary(pos) => {
+                write!(f, "{}バイト目は文字の区切りではありません", pos)
+            }
+        }
+    }
+}
+
+/// 解析済みのブロック
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentBlock {
+    pub kind: BlockKind,
+    pub span: Span,
+    pub result: Result<Parsed, ParseError>,
+}
+
+/// 前回の解析結果を保持する辞書ファイル1つ分の文書
+#[derive(Debug, Clone)]
+pub struct Document {
+    text: String,
+    file: usize,
+    blocks: Vec<DocumentBlock>,
+}
+
+impl Document {
+    pub fn new(text: impl Into<String>, file: usize) -> Self {
+        let text = text.into();
+        let blocks = parse_blocks(&text, file, 0, 1);
+        Self { text, file, blocks }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn blocks(&self) -> &[DocumentBlock] {
+        &self.blocks
+    }
+
+    /// 編集を適用し、影響するブロックだけを解析し直します
+    /// 解析し直したブロックの範囲（編集後のblocksの添字）を返します
+    /// 範囲が不正な場合は何も変更せずにエラーを返します
+    pub fn edit(&mut self, edit: Edit) -> Result<Range<usize>, EditError> {
+        let Edit { range, text } = edit;
+        if range.start > range.end || range.end > self.text.len() {
+            return Err(EditError::OutOfRange(range));
+        }
+        if let Some(pos) = [range.start, range.end]
+            .into_iter()
+            .find(|pos| !self.text.is_char_boundary(*pos))
+        {
+            return Err(EditError::NotCharBoundary(pos));
+        }
+
+        // 編集範囲に接するブロックを探す
+        // ブロックの先頭・末尾ちょうどの編集でも、＊の行頭判定が変わりうるので両側を含める
+        let first = self
+            .blocks
+            .iter()
+            .position(|b| b.span.end >= range.start)
+            .unwrap_or(self.blocks.len());
+        let last = self
+            .blocks
+            .iter()
+            .rposition(|b| b.span.start <= range.end)
+            .map(|i| i + 1)
+            .unwrap_or(0)
+            .max(first);
+
+        // 前置き部分に＊が書かれることもあるので、先頭のブロックからならテキストの先頭から解析する
+        let (region_start, line) = match first {
+            0 => (0, 1),
+            _ => (self.blocks[first].span.start, self.blocks[first].span.line),
+        };
+        let region_end = self
+            .blocks
+            .get(last)
+            .map_or(self.text.len(), |b| b.span.start);
+
+        let removed_lines = count_lines(&self.text[range.clone()]);
+        let added_lines = count_lines(&text);
+        let bytes = text.len() as isize - range.len() as isize;
+        let lines = added_lines as isize - removed_lines as isize;
+
+        self.text.replace_range(range, &text);
+
+        let new_end = (region_end as isize + bytes) as usize;
+        let reparsed = parse_blocks(
+            &self.text[region_start..new_end],
+            self.file,
+            region_start,
+            line,
+        );
+
+        let count = reparsed.len();
+        let mut tail = self.blocks.split_off(last);
+        for block in &mut tail {
+            block.shift(bytes, lines);
+        }
+        self.blocks.truncate(first);
+        self.blocks.extend(reparsed);
+        self.blocks.extend(tail);
+
+        Ok(first..first + count)
+    }
+
+    /// 全ブロックの解析結果をまとめます
+    /// 解析エラーのブロックがあれば最初のエラーを返します
+    pub fn satori(&self) -> Result<ast::Satori, ParseError> {
+        block::strict(self.satori_partial())
+    }
+
+    /// 解析できたブロックをまとめ、エラーのあるブロックはerrorsに記録します
+    pub fn satori_partial(&self) -> ast::Satori {
+        block::collect(self.blocks.iter().map(|block| block.result.clone()))
+    }
+}
+
+impl DocumentBlock {
+    /// 前方の編集に合わせて位置をずらします
+    fn shift(&mut self, bytes: isize, lines: isize) {
+        shift_span(&mut self.span, bytes, lines);
+        match &mut self.result {
+            Ok(Parsed::Talk(talk)) => shift_span(&mut talk.span, bytes, lines),
+            Ok(Parsed::WordGroup(word_group)) => shift_span(&mut word_group.span, bytes, lines),
+            Err(e) => shift_span(&mut e.span, bytes, lines),
+        }
+    }
+}
+
+fn shift_span(span: &mut Span, bytes: isize, lines: isize) {
+    span.start = (span.start as isize + bytes) as usize;
+    span.end = (span.end as isize + bytes) as usize;
+    span.line = (span.line as isize + lines) as usize;
+}
+
+fn count_lines(text: &str) -> usize {
+    text.bytes().filter(|b| *b == b'\n').count()
+}
+
+/// テキストの一部をブロックに分割して解析します
+/// offset・lineは部分テキストの先頭の位置です
+fn parse_blocks(text: &str, file: usize, offset: usize, line: usize) -> Vec<DocumentBlock> {
+    block::split(text, file)
+        .into_iter()
+        .map(|mut block| {
+            shift_span(&mut block.span, offset as isize, line as isize - 1);
+            DocumentBlock {
+                kind: block.kind,
+                span: block.span,
+                result: block::parse_block(&block),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: &str = "＃前置き\n＊OnBoot\nこんにちは\n\n＠天気\n晴れ\n雨\n＊\nやあ\n";
+
+    fn replace(doc: &mut Document, from: &str, to: &str) -> Range<usize> {
+        let start = doc.text().find(from).unwrap();
+        doc.edit(Edit {
+            range: start..start + from.len(),
+            text: to.to_string(),
+        })
+        .unwrap()
+    }
+
+    fn assert_same_as_full_parse(doc: &Document) {
+        let full = Document::new(doc.text(), 0);
+        assert_eq!(doc.blocks(), full.blocks());
+    }
+
+    #[test]
+    fn edit_inside_block() {
+        let mut doc = Document::new(SRC, 0);
+        assert_eq!(doc.blocks().len(), 3);
+
+        let reparsed = replace(&mut doc, "晴れ", "くもり\n雪");
+
+        assert_eq!(reparsed, 1..2);
+        assert_same_as_full_parse(&doc);
+        assert_eq!(doc.satori().unwrap().talk[1].span.line, 9);
+    }
+
+    #[test]
+    fn edit_splits_and_merges_blocks() {
+        let mut doc = Document::new(SRC, 0);
+
+        // 新しいトークを挟む
+        replace(
+            &mut doc,
+            "こんにちは\n",
+            "こんにちは\n＊OnClose\nさようなら\n",
+        );
+        assert_eq!(doc.blocks().len(), 4);
+        assert_same_as_full_parse(&doc);
+
+        // ＊を消して前のブロックとつなげる
+        replace(&mut doc, "＊OnClose\n", "");
+        assert_eq!(doc.blocks().len(), 3);
+        assert_same_as_full_parse(&doc);
+
+        // 改行を消して＊を行頭でなくす
+        replace(&mut doc, "雨\n＊", "雨＊");
+        assert_eq!(doc.blocks().len(), 2);
+        assert_same_as_full_parse(&doc);
+
+        // 前置きの中で＊を書く
+        replace(&mut doc, "＃前置き\n", "＊\n前置き\n");
+        assert_eq!(doc.blocks().len(), 3);
+        assert_same_as_full_parse(&doc);
+
+        // 末尾に追記する
+        let end = doc.text().len();
+        doc.edit(Edit {
+            range: end..end,
+            text: "＠名前\nさくら\n".to_string(),
+        })
+        .unwrap();
+        assert_same_as_full_parse(&doc);
+        assert_eq!(doc.satori().unwrap().word_group.len(), 2);
+    }
+
+    #[test]
+    fn edit_error_block() {
+        let mut doc = Document::new(SRC, 0);
+
+        replace(&mut doc, "やあ", "（やあ");
+        assert!(doc.satori().is_err());
+        assert_same_as_full_parse(&doc);
+
+        let satori = doc.satori_partial();
+        assert_eq!(satori.talk.len(), 1);
+        assert_eq!(satori.word_group.len(), 1);
+        assert_eq!(satori.errors.len(), 1);
+
+        // エラーのあるブロックより前を編集しても位置が追従する
+        replace(&mut doc, "こんにちは", "こんにちは\n\n");
+        assert_eq!(doc.satori().unwrap_err().span.line, 11);
+        assert_same_as_full_parse(&doc);
+
+        replace(&mut doc, "（やあ", "やあ");
+        assert!(doc.satori().is_ok());
+    }
+
+    #[test]
+    fn invalid_edit() {
+        let mut doc = Document::new(SRC, 0);
+        let edit = |range: Range<usize>| Edit {
+            range,
+            text: String::new(),
+        };
+
+        let len = SRC.len();
+        assert_eq!(
+            doc.edit(edit(len..len + 1)),
+            Err(EditError::OutOfRange(len..len + 1))
+        );
+        #[allow(clippy::reversed_empty_ranges)]
+        let reversed = 3..0;
+        assert_eq!(
+            doc.edit(edit(reversed.clone())),
+            Err(EditError::OutOfRange(reversed))
+        );
+        // ＃は3バイト
+        assert_eq!(doc.edit(edit(1..3)), Err(EditError::NotCharBoundary(1)));
+        assert_eq!(doc.text(), SRC);
+        assert_same_as_full_parse(&doc);
+    }
+}
@@ -2,7 +2,12 @@ use std::{iter::Peekable, str::CharIndices};
 
 use token::*;
 
-mod token;
+pub use lexer::Number;
+
+pub mod block;
+pub mod dictionary;
+pub mod incremental;
+pub mod token;
 
 use lalrpop_util::lalrpop_mod;
 
@@ -16,14 +21,19 @@ pub enum Token {
     Colon,
     At,
     Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
     Plus,
-    // Minus,
-    // Mul,
-    // Div,
-    // Mod,
+    Minus,
+    Mul,
+    Div,
+    Mod,
     Cacco,
     Cocca,
-    Number(f32),
+    Number(Number),
     Identifier(String),
 }
 
@@ -50,95 +60,71 @@ impl<'input> Iterator for Lexer<'input> {
         ) -> Option<Result<(usize, Token, usize), LexicalError>> {
             Some(Ok((s, token, e)))
         }
+        // 語の間の空白は読み飛ばす
+        while self
+            .chars
+            .next_if(|(_, c)| matches!(c, ' ' | '　'))
+            .is_some()
+        {}
         match self.chars.next() {
-            Some((i, ' ')) => tik(i, Token::Space, i + ' '.len_utf8()),
             Some((i, '\t')) => tik(i, Token::Tab, i + '\t'.len_utf8()),
             Some((i, '\r')) => tik(i, Token::CarriageReturn, i + '\r'.len_utf8()),
             Some((i, '\n')) => tik(i, Token::LineFeed, i + '\n'.len_utf8()),
             Some((i, '＊')) => tik(i, Token::Asterisk, i + '＊'.len_utf8()),
             Some((i, '＠')) => tik(i, Token::At, i + '＠'.len_utf8()),
             Some((i, '：')) => tik(i, Token::Colon, i + '：'.len_utf8()),
-            Some((i, '＝')) => match self.chars.next() {
-                Some((_, '＝')) => tik(i, Token::Equal, i + '＝'.len_utf8() * 2),
-                Some((_, c)) => Some(Err(LexicalError::UnexpectedCharacter(
-                    i,
-                    c,
-                    i + '＝'.len_utf8() * 2,
-                ))),
-                None => Some(Err(LexicalError::UnexpectedCharacter(
-                    i,
-                    '＝',
-                    i + '＝'.len_utf8() * 2,
-                ))),
-            },
+            // 比較演算子（＝＝、！＝、＜＝、＞＝は半角の=でも書ける）
+            Some((i, c @ ('＝' | '=' | '！' | '!' | '＜' | '<' | '＞' | '>'))) => {
+                let mut e = i + c.len_utf8();
+                let equal = self.chars.next_if(|(_, c)| matches!(c, '＝' | '='));
+                if let Some((_, c)) = equal {
+                    e += c.len_utf8();
+                }
+                let token = match (c, equal.is_some()) {
+                    ('＝' | '=', true) => Token::Equal,
+                    ('！' | '!', true) => Token::NotEqual,
+                    ('＜' | '<', true) => Token::LessEqual,
+                    ('＞' | '>', true) => Token::GreaterEqual,
+                    ('＜' | '<', false) => Token::Less,
+                    ('＞' | '>', false) => Token::Greater,
+                    _ => return Some(Err(LexicalError::UnexpectedCharacter(i, c, e))),
+                };
+                tik(i, token, e)
+            }
+            Some((i, '≦')) => tik(i, Token::LessEqual, i + '≦'.len_utf8()),
+            Some((i, '≧')) => tik(i, Token::GreaterEqual, i + '≧'.len_utf8()),
             Some((i, '＋')) => tik(i, Token::Plus, i + '＋'.len_utf8()),
-            // Some((i, '－')) => tik(i, Token::Minus, i + '－'.len_utf8()),
-            // Some((i, '×')) => tik(i, Token::Mul, i + '×'.len_utf8()),
-            // Some((i, '／')) => tik(i, Token::Div, i + '／'.len_utf8()),
-            // Some((i, '％')) => tik(i, Token::Mod, i + '％'.len_utf8()),
+            Some((i, '－')) => tik(i, Token::Minus, i + '－'.len_utf8()),
+            Some((i, '×')) => tik(i, Token::Mul, i + '×'.len_utf8()),
+            Some((i, c @ ('／' | '÷'))) => tik(i, Token::Div, i + c.len_utf8()),
+            Some((i, '％')) => tik(i, Token::Mod, i + '％'.len_utf8()),
             Some((i, '（')) => tik(i, Token::Cacco, i + '（'.len_utf8()),
             Some((i, '）')) => tik(i, Token::Cocca, i + '）'.len_utf8()),
-            // Some((s, c)) => {
-            //     if c.is_numeric() || c == '.' {
-            //         let mut num = c.to_string();
-            //         let mut e = s + c.len_utf8();
-            //         let mut has_dot = c == '.';
-            //         while let Some((_, c)) = self.chars.peek() {
-            //             if !c.is_numeric() && *c != '.' {
-            //                 return tik(s, Token::Number(num.parse().unwrap()), e);
-            //             }
-
-            //             if *c == '.' {
-            //                 if has_dot {
-            //                     return Some(Err(LexicalError::UnexpectedCharacter(s, *c, s + 1)));
-            //                 }
-            //                 has_dot = true;
-            //             }
-
-            //             e += c.len_utf8();
-            //             num.push(*c);
-            //             self.chars.next();
-            //         }
-            //         tik(s, Token::Number(num.parse().unwrap()), e)
-            //     } else {
-            //         Some(Err(LexicalError::UnexpectedCharacter(s, c, s + 1)))
-            //     }
-            // }
+            // 演算子・カッコ・改行までを1語とする（語の中の空白や句読点も含める）
+            // 数字だけの語は数値です
             Some((s, c)) => {
-                if c.is_numeric() || c == '.' {
-                    let mut num = c.to_string();
-                    let mut e = s + c.len_utf8();
-                    let mut has_dot = c == '.';
-                    while let Some((_, c)) = self.chars.peek() {
-                        if !c.is_numeric() && *c != '.' {
-                            return tik(s, Token::Number(num.parse().unwrap()), e);
-                        }
-
-                        if *c == '.' {
-                            if has_dot {
-                                return Some(Err(LexicalError::UnexpectedCharacter(s, *c, s + 1)));
-                            }
-                            has_dot = true;
-                        }
-
-                        e += c.len_utf8();
-                        num.push(*c);
-                        self.chars.next();
+                let mut word = c.to_string();
+                let mut e = s + c.len_utf8();
+                let mut spaces = String::new();
+                while let Some(&(i, c)) = self.chars.peek() {
+                    if is_delimiter(c) {
+                        break;
                     }
-                    tik(s, Token::Number(num.parse().unwrap()), e)
-                } else if c.is_alphabetic() {
-                    let mut iden = c.to_string();
-                    let mut e = s + c.len_utf8();
-                    while let Some((_, c)) = self.chars.peek() {
-                        if !c.is_alphabetic() {
-                            return tik(s, Token::Identifier(iden), e);
-                        }
-                        e += c.len_utf8();
-                        iden.push(self.chars.next().unwrap().1)
+                    self.chars.next();
+                    if matches!(c, ' ' | '　') {
+                        spaces.push(c);
+                        continue;
                     }
-                    tik(s, Token::Identifier(iden), e)
-                } else {
-                    Some(Err(LexicalError::UnexpectedCharacter(s, c, s + 1)))
+                    word.push_str(&std::mem::take(&mut spaces));
+                    word.push(c);
+                    e = i + c.len_utf8();
+                }
+                if !word.chars().all(is_number_char) {
+                    return tik(s, Token::Identifier(word), e);
+                }
+                match Number::parse(&word) {
+                    Some(n) => tik(s, Token::Number(n), e),
+                    None => Some(Err(LexicalError::InvalidNumber(s, word, e))),
                 }
             }
             None => None,
@@ -146,77 +132,191 @@ impl<'input> Iterator for Lexer<'input> {
     }
 }
 
+/// 語の区切りになる文字か判定します
+fn is_delimiter(c: char) -> bool {
+    matches!(
+        c,
+        '\t' | '\r'
+            | '\n'
+            | '＊'
+            | '＠'
+            | '：'
+            | '＝'
+            | '='
+            | '！'
+            | '!'
+            | '＜'
+            | '<'
+            | '＞'
+            | '>'
+            | '≦'
+            | '≧'
+            | '＋'
+            | '－'
+            | '×'
+            | '／'
+            | '÷'
+            | '％'
+            | '（'
+            | '）'
+    )
+}
+
+/// 数値の一部になる文字か判定します（全角の数字・小数点を含む）
+fn is_number_char(c: char) -> bool {
+    c.is_ascii_digit() || ('０'..='９').contains(&c) || c == '.' || c == '．'
+}
+
 #[derive(Debug)]
 pub enum LexicalError {
     UnexpectedCharacter(usize, char, usize),
+    InvalidNumber(usize, String, usize),
 }
 
 pub mod ast {
-    #[derive(Debug)]
+    use crate::{token::Content, Number};
+
+    /// 辞書ソース上の位置
+    #[derive(Debug, Clone, Copy, PartialEq, Default)]
+    pub struct Span {
+        pub file: usize,  // 読み込み元ファイルの番号
+        pub start: usize, // 開始バイト位置
+        pub end: usize,   // 終了バイト位置
+        pub line: usize,  // 開始行番号（1始まり）
+    }
+
+    /// トーク・単語群の1行分の内容
+    pub type Line = Vec<Content>;
+
+    #[derive(Debug, Clone, PartialEq, Default)]
     pub struct Satori {
         pub talk: Vec<Talk>,
         pub word_group: Vec<WordGroup>,
+        pub errors: Vec<crate::block::ParseError>, // 解析できずに飛ばしたブロックのエラー
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone, PartialEq)]
     pub struct Talk {
         pub start: TalkStart,
-        pub contents: Vec<Expression>,
+        pub contents: Vec<Line>,
+        pub span: Span,
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone, PartialEq)]
     pub struct TalkStart {
-        pub label: Option<Expression>,
-        pub condition: Option<Expression>,
+        pub label: Option<String>,         // トーク名（無名の＊はNone）
+        pub tags: Vec<String>,             // ≧で検索するタグ
+        pub condition: Option<Expression>, // 条件式
+        pub kind: TalkKind,
+    }
+
+    /// トークの種類
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum TalkKind {
+        Random, // 無名の＊（ランダムトーク）
+        Event,  // OnBootなどのイベント名
+        Named,  // それ以外の名前付きトーク
+    }
+
+    impl TalkStart {
+        pub fn new(
+            label: Option<String>,
+            tags: Vec<String>,
+            condition: Option<Expression>,
+        ) -> Self {
+            let kind = match &label {
+                None => TalkKind::Random,
+                Some(label) if is_event_name(label) => TalkKind::Event,
+                Some(_) => TalkKind::Named,
+            };
+            Self {
+                label,
+                tags,
+                condition,
+                kind,
+            }
+        }
+
+        pub fn name(&self) -> Option<&str> {
+            self.label.as_deref()
+        }
+
+        pub fn has_tag(&self, tag: &str) -> bool {
+            self.tags.iter().any(|t| t == tag)
+        }
+    }
+
+    /// イベント名か判定します
+    /// 里々と同じく On + 英大文字で始まる名前をイベントとして扱います
+    pub fn is_event_name(label: &str) -> bool {
+        label
+            .strip_prefix("On")
+            .and_then(|rest| rest.chars().next())
+            .is_some_and(|c| c.is_ascii_uppercase())
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone, PartialEq)]
     pub struct WordGroup {
-        pub label: Expression,
-        pub contents: Vec<Expression>,
+        pub label: String,
+        pub condition: Option<Expression>,
+        pub contents: Vec<Line>,
+        pub span: Span,
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone, PartialEq)]
     pub enum Expression {
+        Comparison(Comparison), // ＝＝、＜など（＋－より弱く結びつく）
         Binary(BinaryExpression),
         Term(Term),
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Comparison {
+        pub lhs: Box<Expression>,
+        pub op: Op,
+        pub rhs: Box<Expression>,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
     pub struct BinaryExpression {
         pub lhs: Box<Expression>,
         pub op: Op,
         pub rhs: Term,
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone, PartialEq)]
     pub enum Term {
         Binary(BinaryTerm),
         Factor(Factor),
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone, PartialEq)]
     pub struct BinaryTerm {
         pub lhs: Box<Term>,
         pub op: Op,
         pub rhs: Factor,
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone, PartialEq)]
     pub enum Factor {
         Expression(Box<Expression>),
         String(String),
-        Number(f32),
+        Number(Number),
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone, PartialEq)]
     pub enum Op {
         Plus,
         Minus,
         Mul,
         Div,
         Mod,
-        Equal,
+        Equal,        // ＝＝
+        NotEqual,     // ！＝
+        Less,         // ＜
+        LessEqual,    // ＜＝、≦
+        Greater,      // ＞
+        GreaterEqual, // ＞＝、≧
     }
 }
 
@@ -256,6 +356,54 @@ mod tests {
         assert_eq!(true, false);
     }
 
+    #[test]
+    fn identifier_test() {
+        let tokens = Lexer::new("R0＝＝４２")
+            .map(|r| r.map(|(_, t, _)| t).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Identifier("R0".to_string()),
+                Token::Equal,
+                Token::Number(Number::Integer(42)),
+            ]
+        );
+    }
+
+    #[test]
+    fn comparison_token_test() {
+        let tokens = |src| {
+            Lexer::new(src)
+                .map(|r| r.map(|(_, t, _)| t).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            tokens("＜ ＜＝ ＞ ＞＝ ！＝ ≦ ≧ <= != =="),
+            vec![
+                Token::Less,
+                Token::LessEqual,
+                Token::Greater,
+                Token::GreaterEqual,
+                Token::NotEqual,
+                Token::LessEqual,
+                Token::GreaterEqual,
+                Token::LessEqual,
+                Token::NotEqual,
+                Token::Equal,
+            ]
+        );
+        // 語の中の空白や句読点は語の一部、前後の空白は読み飛ばす
+        assert_eq!(
+            tokens(" さくら さん。 ＝＝ １０個"),
+            vec![
+                Token::Identifier("さくら さん。".to_string()),
+                Token::Equal,
+                Token::Identifier("１０個".to_string()),
+            ]
+        );
+    }
+
     #[test]
     pub fn it_works() {
         let result = parse(
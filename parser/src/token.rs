@@ -1,4 +1,6 @@
-use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::{ast::Expression, Number};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
@@ -40,6 +42,7 @@ pub enum Content {
     UserSelections(UserSelection),            // ＿
     TalkWithOtherGhost(TalkWithOtherGhost),   // →
     Sentense(String),                         // 文
+    Condition(Expression),                    // タブ区切りの条件式
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -50,7 +53,7 @@ pub enum VariableDeclaration {
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Primitives {
-    Number(f32),    // 数値
+    Number(Number), // 数値
     String(String), // 文字列
 }
 
@@ -81,7 +84,7 @@ pub enum WordGroupContent {
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Macro {
-    Macro(Rc<Macro>),               // マクロ展開式
+    Macro(Arc<Macro>),              // マクロ展開式
     SurfaceChange(String),          // （[0-9０-９]）
     TalkCalling(String),            // トークラベル名
     FunctionCall(FunctionCall),     // 関数呼び出し
@@ -89,9 +92,9 @@ pub enum Macro {
 }
 
 #[derive(Debug, PartialEq, Clone)]
-pub enum FunctionCall {
-    FunctionName(String),  // 関数名
-    Argument(Vec<String>), // 関数引数
+pub struct FunctionCall {
+    pub name: String,                 // 関数名
+    pub arguments: Vec<Vec<Content>>, // 関数引数
 }
 
 #[derive(Debug, PartialEq, Clone)]
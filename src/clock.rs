@@ -0,0 +1,147 @@
+//! 日時関係の変数が読む時計
+//! テストではFakeClockを渡すと日時を固定できます
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{Local, NaiveDate, NaiveDateTime};
+
+pub trait Clock {
+    /// 現在のローカル日時
+    fn now(&self) -> NaiveDateTime;
+
+    /// Unix時間（秒）
+    fn unix_time(&self) -> i64;
+
+    /// OSが起動してからの経過時間
+    fn uptime(&self) -> Duration;
+}
+
+/// 実際の時計
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        Local::now().naive_local()
+    }
+
+    fn unix_time(&self) -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default()
+    }
+
+    fn uptime(&self) -> Duration {
+        os_uptime().unwrap_or_default()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn os_uptime() -> Option<Duration> {
+    let uptime = std::fs::read_to_string("/proc/uptime").ok()?;
+    let seconds = uptime.split_whitespace().next()?.parse::<f64>().ok()?;
+    Some(Duration::from_secs_f64(seconds))
+}
+
+#[cfg(windows)]
+fn os_uptime() -> Option<Duration> {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetTickCount64() -> u64;
+    }
+    // SAFETY: 引数がなく、常に成功する
+    Some(Duration::from_millis(unsafe { GetTickCount64() }))
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn os_uptime() -> Option<Duration> {
+    None
+}
+
+/// 日時を固定した時計
+/// cloneしたものは同じ日時を共有するので、Runtimeに渡した後でも進められます
+#[derive(Debug, Clone)]
+pub struct FakeClock {
+    now: Arc<Mutex<NaiveDateTime>>,
+    uptime: Arc<Mutex<Duration>>,
+}
+
+impl FakeClock {
+    pub fn new(now: NaiveDateTime) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+            uptime: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    /// 年月日・時分秒から作ります
+    /// 存在しない日時の場合はNoneを返します
+    pub fn at(
+        year: i32,
+        month: u32,
+        day: u32,
+        hour: u32,
+        minute: u32,
+        second: u32,
+    ) -> Option<Self> {
+        let now = NaiveDate::from_ymd_opt(year, month, day)?.and_hms_opt(hour, minute, second)?;
+        Some(Self::new(now))
+    }
+
+    pub fn set(&self, now: NaiveDateTime) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    /// 時計とOSの起動時間を進めます
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+        *self.uptime.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> NaiveDateTime {
+        *self.now.lock().unwrap()
+    }
+
+    /// ローカル日時をUTCとみなしたUnix時間です
+    fn unix_time(&self) -> i64 {
+        self.now().and_utc().timestamp()
+    }
+
+    fn uptime(&self) -> Duration {
+        *self.uptime.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Datelike, Timelike, Weekday};
+
+    use super::*;
+
+    #[test]
+    fn fake_clock_test() {
+        let clock = FakeClock::at(2024, 4, 1, 7, 0, 0).unwrap();
+        let shared = clock.clone();
+        assert_eq!(clock.now().weekday(), Weekday::Mon);
+        assert_eq!(clock.unix_time(), 1_711_954_800);
+
+        shared.advance(Duration::from_secs(90));
+        assert_eq!(clock.now().minute(), 1);
+        assert_eq!(clock.now().second(), 30);
+        assert_eq!(clock.uptime(), Duration::from_secs(90));
+
+        assert!(FakeClock::at(2024, 2, 30, 0, 0, 0).is_none());
+    }
+
+    #[test]
+    fn system_clock_test() {
+        let clock = SystemClock;
+        assert!(clock.unix_time() > 1_700_000_000);
+    }
+}
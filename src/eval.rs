@@ -0,0 +1,403 @@
+//! 条件式（ast::Expression）を評価します
+
+use std::ops::Range;
+
+use parser::{
+    ast::{BinaryExpression, BinaryTerm, Comparison, Expression, Factor, Op, Term},
+    Number,
+};
+
+use crate::runtime::Error;
+
+/// （…）の展開を行う関数
+pub type Expand<'a> = dyn FnMut(&str) -> Result<String, Error> + 'a;
+
+/// 式を評価して値を文字列で返します
+pub fn evaluate(expr: &Expression, expand: &mut Expand) -> Result<String, Error> {
+    match expr {
+        Expression::Term(term) => evaluate_term(term, expand),
+        Expression::Comparison(Comparison { lhs, op, rhs }) => {
+            let lhs = evaluate(lhs, expand)?;
+            let rhs = evaluate(rhs, expand)?;
+            operate(&lhs, op, &rhs)
+        }
+        Expression::Binary(BinaryExpression { lhs, op, rhs }) => {
+            let lhs = evaluate(lhs, expand)?;
+            let rhs = evaluate_term(rhs, expand)?;
+            operate(&lhs, op, &rhs)
+        }
+    }
+}
+
+fn evaluate_term(term: &Term, expand: &mut Expand) -> Result<String, Error> {
+    match term {
+        Term::Factor(factor) => evaluate_factor(factor, expand),
+        Term::Binary(BinaryTerm { lhs, op, rhs }) => {
+            let lhs = evaluate_term(lhs, expand)?;
+            let rhs = evaluate_factor(rhs, expand)?;
+            operate(&lhs, op, &rhs)
+        }
+    }
+}
+
+fn evaluate_factor(factor: &Factor, expand: &mut Expand) -> Result<String, Error> {
+    match factor {
+        // 全角カッコは里々の展開なので、中身を名前として展開する
+        Factor::Expression(expr) => {
+            let name = evaluate(expr, expand)?;
+            expand(&name)
+        }
+        Factor::String(s) => Ok(s.clone()),
+        Factor::Number(n) => Ok(n.to_string()),
+    }
+}
+
+fn operate(lhs: &str, op: &Op, rhs: &str) -> Result<String, Error> {
+    let numbers = Number::parse(lhs).zip(Number::parse(rhs));
+    // 数値として読めない値は四則演算では0として扱う
+    let (l, r) = (Number::coerce(lhs), Number::coerce(rhs));
+    Ok(match op {
+        Op::Plus => match numbers {
+            Some((l, r)) => (l + r).to_string(),
+            None => format!("{}{}", lhs, rhs),
+        },
+        Op::Minus => (l - r).to_string(),
+        Op::Mul => (l * r).to_string(),
+        Op::Div => l.checked_div(r).ok_or_else(division_by_zero)?.to_string(),
+        Op::Mod => l.checked_rem(r).ok_or_else(division_by_zero)?.to_string(),
+        Op::Equal | Op::NotEqual | Op::Less | Op::LessEqual | Op::Greater | Op::GreaterEqual => {
+            bool_value(compare(lhs, op, rhs))
+        }
+    })
+}
+
+fn division_by_zero() -> Error {
+    Error::Evaluation("0で割ることはできません".to_string())
+}
+
+fn bool_value(b: bool) -> String {
+    if b { "1" } else { "0" }.to_string()
+}
+
+/// 文字列を式として計算します（（計算）関数）
+pub fn calculate_text(text: &str) -> Result<String, Error> {
+    let expr = parser::satori::ComparisonParser::new()
+        .parse(parser::Lexer::new(text.trim()))
+        .map_err(|_| Error::Evaluation(format!("計算式ではありません: {}", text)))?;
+    evaluate(&expr, &mut |_| Ok(String::new()))
+}
+
+/// 数値と演算子だけでできた計算式なら計算します
+/// 計算式でなければNoneを返します
+pub fn calculate(text: &str) -> Option<Result<String, Error>> {
+    let expr = parser::satori::ComparisonParser::new()
+        .parse(parser::Lexer::new(text))
+        .ok()?;
+    (is_formula(&expr) && operands(&expr, &mut |f| matches!(f, Factor::Number(_))))
+        .then(|| evaluate(&expr, &mut |_| Ok(String::new())))
+}
+
+/// ＄行の右辺の一部
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Part<'a> {
+    Text(&'a str),     // 辞書に書かれた文
+    Expanded(&'a str), // （…）を展開した値
+}
+
+/// 展開した値を埋め込む名前
+const PLACEHOLDER: &str = "展開";
+
+/// 辞書に書かれたとおりの式を、展開した値を被演算子として計算します
+/// 展開した値の中の記号は演算子とみなさず、空の展開は0とします
+/// 数値と展開した数値だけの計算式でなければNoneを返します
+pub fn calculate_parts(parts: &[Part]) -> Option<Result<String, Error>> {
+    let mut formula = String::new();
+    let mut values = vec![];
+    for part in parts {
+        match part {
+            Part::Text(text) => formula.push_str(text),
+            Part::Expanded(value) => {
+                formula.push_str(&format!("（{}{}）", PLACEHOLDER, values.len()));
+                values.push(if value.is_empty() { "0" } else { value });
+            }
+        }
+    }
+    let expr = parser::satori::ComparisonParser::new()
+        .parse(parser::Lexer::new(&formula))
+        .ok()?;
+    let value = |name: &str| {
+        let n = name.strip_prefix(PLACEHOLDER)?.parse::<usize>().ok()?;
+        values.get(n).copied()
+    };
+    let numeric = operands(&expr, &mut |f| match f {
+        Factor::Number(_) => true,
+        Factor::Expression(expr) => match expr.as_ref() {
+            Expression::Term(Term::Factor(Factor::String(name))) => {
+                value(name).is_some_and(|v| Number::parse(v).is_some())
+            }
+            _ => false,
+        },
+        Factor::String(_) => false,
+    });
+    (is_formula(&expr) && numeric).then(|| {
+        evaluate(&expr, &mut |name| {
+            Ok(value(name).unwrap_or_default().to_string())
+        })
+    })
+}
+
+/// 演算子を含む式か判定します
+fn is_formula(expr: &Expression) -> bool {
+    !matches!(expr, Expression::Term(Term::Factor(_)))
+}
+
+/// 式の被演算子がすべて条件を満たすか判定します
+fn operands(expr: &Expression, check: &mut dyn FnMut(&Factor) -> bool) -> bool {
+    fn term(t: &Term, check: &mut dyn FnMut(&Factor) -> bool) -> bool {
+        match t {
+            Term::Factor(f) => check(f),
+            Term::Binary(BinaryTerm { lhs, rhs, .. }) => term(lhs, check) && check(rhs),
+        }
+    }
+    match expr {
+        Expression::Term(t) => term(t, check),
+        Expression::Comparison(Comparison { lhs, rhs, .. }) => {
+            operands(lhs, check) && operands(rhs, check)
+        }
+        Expression::Binary(BinaryExpression { lhs, rhs, .. }) => {
+            operands(lhs, check) && term(rhs, check)
+        }
+    }
+}
+
+/// 文字列中の比較演算子（2文字のものを先に探す）
+const COMPARATORS: [(&str, Op); 14] = [
+    ("＝＝", Op::Equal),
+    ("！＝", Op::NotEqual),
+    ("＜＝", Op::LessEqual),
+    ("＞＝", Op::GreaterEqual),
+    ("==", Op::Equal),
+    ("!=", Op::NotEqual),
+    ("<=", Op::LessEqual),
+    (">=", Op::GreaterEqual),
+    ("≦", Op::LessEqual),
+    ("≧", Op::GreaterEqual),
+    ("＜", Op::Less),
+    ("＞", Op::Greater),
+    ("<", Op::Less),
+    (">", Op::Greater),
+];
+
+/// 値を比較します
+/// 両方が数値なら数値として、そうでなければ文字列として比較します
+/// 条件式・iflist・whenの比較はすべてここで行います
+pub fn compare(lhs: &str, op: &Op, rhs: &str) -> bool {
+    let ordering = match Number::parse(lhs).zip(Number::parse(rhs)) {
+        Some((l, r)) => l.partial_cmp(&r),
+        None => Some(lhs.cmp(rhs)),
+    };
+    let Some(ordering) = ordering else {
+        return false;
+    };
+    match op {
+        Op::Equal => ordering.is_eq(),
+        Op::NotEqual => ordering.is_ne(),
+        Op::LessEqual => ordering.is_le(),
+        Op::GreaterEqual => ordering.is_ge(),
+        Op::Less => ordering.is_lt(),
+        Op::Greater => ordering.is_gt(),
+        _ => false,
+    }
+}
+
+/// 「＜６」のような比較を値に当てはめます（iflistの条件）
+/// 演算子がなければ等しいかどうかを判定します
+pub fn matches(value: &str, condition: &str) -> bool {
+    let condition = condition.trim();
+    COMPARATORS
+        .iter()
+        .chain(&[("＝", Op::Equal), ("=", Op::Equal)])
+        .find_map(|(op, kind)| condition.strip_prefix(op).map(|rhs| (kind, rhs)))
+        .map_or_else(
+            || compare(value, &Op::Equal, condition),
+            |(op, rhs)| compare(value, op, rhs.trim()),
+        )
+}
+
+/// 最も左にある比較演算子のバイト範囲と演算子を返します
+/// 同じ位置なら長いもの（＜＝など）を優先します
+pub fn find_comparator(text: &str) -> Option<(Range<usize>, Op)> {
+    COMPARATORS
+        .iter()
+        .filter_map(|(op, kind)| text.find(op).map(|i| (i..i + op.len(), kind)))
+        .min_by_key(|(range, _)| (range.start, std::cmp::Reverse(range.end)))
+        .map(|(range, op)| (range, op.clone()))
+}
+
+/// 展開済みの両辺を計算してから比較します（whenの条件）
+pub fn compare_sides(lhs: &str, op: &Op, rhs: &str) -> Result<bool, Error> {
+    Ok(compare(&side(lhs)?, op, &side(rhs)?))
+}
+
+/// 展開済みの値を計算し、真かどうか判定します（whenの条件）
+pub fn truth(text: &str) -> Result<bool, Error> {
+    Ok(is_true(&side(text)?))
+}
+
+/// 計算式なら計算し、そうでなければそのまま返します
+fn side(text: &str) -> Result<String, Error> {
+    match calculate(text.trim()) {
+        Some(result) => result,
+        None => Ok(text.trim().to_string()),
+    }
+}
+
+/// 展開済みの条件文字列を判定します
+/// 比較演算子があれば両辺を比較し、なければ値が真かどうかで判定します
+pub fn condition(text: &str) -> Result<bool, Error> {
+    match find_comparator(text) {
+        Some((range, op)) => compare_sides(&text[..range.start], &op, &text[range.end..]),
+        None => truth(text),
+    }
+}
+
+/// 条件式の結果を真偽値として判定します
+/// 数値なら0以外、それ以外は空でなければ真です
+pub fn is_true(value: &str) -> bool {
+    Number::parse(value).map_or(!value.is_empty(), |n| n.is_truthy())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(src: &str) -> String {
+        let expr = parser::satori::ComparisonParser::new()
+            .parse(parser::Lexer::new(src))
+            .unwrap();
+        evaluate(&expr, &mut |name| {
+            Ok(match name {
+                "回数" => "１０".to_string(),
+                _ => String::new(),
+            })
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn evaluate_test() {
+        assert_eq!(eval("1＋2"), "3");
+        assert_eq!(eval("さくら＋1"), "さくら1");
+        assert_eq!(eval("（回数）＝＝10"), "1");
+        assert_eq!(eval("（回数）＝＝11"), "0");
+        assert_eq!(eval("（未定義）＝＝さくら"), "0");
+    }
+
+    #[test]
+    fn arithmetic_test() {
+        assert_eq!(eval("１０－３×２"), "4");
+        assert_eq!(eval("7／2"), "3.5");
+        assert_eq!(eval("6÷2"), "3");
+        assert_eq!(eval("7％3"), "1");
+        assert_eq!(eval("1.5＋1.5"), "3");
+        assert_eq!(eval("－2×（回数）"), "-20");
+        assert_eq!(eval("さくら×2"), "0");
+        assert_eq!(eval("（回数）×2＝＝２０"), "1");
+    }
+
+    #[test]
+    fn comparison_test() {
+        // ＝＝は＋－より後に計算する
+        assert_eq!(eval("1＋1＝＝3"), "0");
+        assert_eq!(eval("1＋1＝＝2"), "1");
+        assert_eq!(eval("10－1＝＝9"), "1");
+        assert_eq!(eval("（回数）－１＝＝５"), "0");
+        assert_eq!(eval("4＝＝2＋2"), "1");
+        assert_eq!(eval("3－1＝＝4－2"), "1");
+        assert_eq!(calculate_text("1＋1＝＝3").unwrap(), "0");
+        assert_eq!(calculate("10－1＝＝9").unwrap().unwrap(), "1");
+    }
+
+    #[test]
+    fn division_by_zero_test() {
+        let expr = parser::satori::ComparisonParser::new()
+            .parse(parser::Lexer::new("1／0"))
+            .unwrap();
+        assert!(matches!(
+            evaluate(&expr, &mut |_| Ok(String::new())),
+            Err(Error::Evaluation(_))
+        ));
+        assert!(calculate("1％０").unwrap().is_err());
+    }
+
+    #[test]
+    fn calculate_text_test() {
+        assert_eq!(calculate_text("１＋２×３").unwrap(), "7");
+        assert_eq!(calculate_text("0.1＋0.2").unwrap(), "0.3");
+        assert_eq!(calculate_text("さくら＝＝さくら").unwrap(), "1");
+        assert!(calculate_text("1＋").is_err());
+    }
+
+    #[test]
+    fn calculate_test() {
+        assert_eq!(calculate("１＋２×３").unwrap().unwrap(), "7");
+        assert_eq!(calculate("10"), None);
+        assert_eq!(calculate("さくら＋うにゅう"), None);
+        assert_eq!(calculate("こんにちは。"), None);
+    }
+
+    #[test]
+    fn calculate_parts_test() {
+        use Part::*;
+        assert_eq!(
+            calculate_parts(&[Expanded("3"), Text("＋"), Expanded("3")])
+                .unwrap()
+                .unwrap(),
+            "6"
+        );
+        assert_eq!(
+            calculate_parts(&[Expanded(""), Text("＋１")])
+                .unwrap()
+                .unwrap(),
+            "1"
+        );
+        // 展開した値の中の記号は演算子にならない
+        assert_eq!(calculate_parts(&[Expanded("２０２４／４／１")]), None);
+        assert_eq!(calculate_parts(&[Expanded("03－1234"), Text("＋１")]), None);
+        assert_eq!(calculate_parts(&[Text("さくら＋"), Expanded("1")]), None);
+    }
+
+    #[test]
+    fn compare_test() {
+        assert!(matches("5", "＜６"));
+        assert!(!matches("１１", "＜１１"));
+        assert!(matches("１１", "≦11"));
+        assert!(matches("さくら", "さくら"));
+        assert!(matches("さくら", "！＝うにゅう"));
+        assert!(matches("10", "＞9"));
+        assert!(!matches("10", "＞さくら"));
+    }
+
+    #[test]
+    fn condition_test() {
+        assert!(condition("1＋1＝＝2").unwrap());
+        assert!(condition("3＞2").unwrap());
+        assert!(!condition("さくら＝＝うにゅう").unwrap());
+        assert!(condition("さくら").unwrap());
+        assert!(!condition("0").unwrap());
+        assert!(!condition("").unwrap());
+        assert!(condition("1／0＝＝1").is_err());
+        // 最も左の演算子で分ける
+        assert!(condition("2＞1＝＝1").unwrap());
+        assert_eq!(find_comparator("a＜＝b＝＝c"), Some((1..7, Op::LessEqual)));
+    }
+
+    #[test]
+    fn is_true_test() {
+        assert!(is_true("1"));
+        assert!(is_true("さくら"));
+        assert!(!is_true("0"));
+        assert!(!is_true("0.0"));
+        assert!(!is_true(""));
+    }
+}
@@ -1 +1,16 @@
-
+pub mod clock;
+pub mod eval;
+pub mod native;
+pub mod random;
+pub mod runtime;
+pub mod savedata;
+pub mod selector;
+pub mod shiori;
+pub mod text;
+pub mod time;
+pub mod variable;
+pub mod vocabulary;
+pub mod word;
+
+#[cfg(test)]
+mod test_util;
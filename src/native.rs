@@ -0,0 +1,348 @@
+//! ベースウェアからshiori.dllとして呼ばれる関数（load、unload、request）
+//! 受け取ったメモリはこちらで解放し、返すメモリはこちらで確保してベースウェアが解放します
+//! Windows以外ではGlobalAlloc/GlobalFreeの代わりにmalloc/freeを使うので、.soとしてテストできます
+//! パニックはベースウェアまで伝えず、0か500のレスポンスにします
+
+use std::{
+    ffi::{c_int, c_long, c_void},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    ptr, slice,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use parser::dictionary::{self, Dictionary};
+
+use crate::{
+    runtime::Runtime,
+    savedata::SaveData,
+    shiori::{ErrorLevel, Request, Response},
+};
+
+/// GlobalAllocで確保したメモリ
+pub type HGlobal = *mut c_void;
+
+const TRUE: c_int = 1;
+const FALSE: c_int = 0;
+
+/// 自動保存の間隔
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 辞書の読み込みエラーを問い合わせるリクエストのID
+const GET_DICTIONARY_ERRORS: &str = "GetDictionaryErrors";
+
+/// ベースウェアとやりとりするメモリの確保・解放
+#[derive(Debug, Clone, Copy)]
+pub struct Allocator {
+    pub alloc: unsafe fn(usize) -> HGlobal,
+    pub free: unsafe fn(HGlobal),
+}
+
+impl Allocator {
+    /// WindowsではGlobalAlloc/GlobalFree、それ以外ではmalloc/free
+    pub const fn system() -> Self {
+        Self {
+            alloc: system::alloc,
+            free: system::free,
+        }
+    }
+}
+
+#[cfg(windows)]
+mod system {
+    use super::HGlobal;
+
+    const GMEM_FIXED: u32 = 0;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GlobalAlloc(flags: u32, bytes: usize) -> HGlobal;
+        fn GlobalFree(memory: HGlobal) -> HGlobal;
+    }
+
+    pub unsafe fn alloc(size: usize) -> HGlobal {
+        GlobalAlloc(GMEM_FIXED, size)
+    }
+
+    pub unsafe fn free(memory: HGlobal) {
+        GlobalFree(memory);
+    }
+}
+
+#[cfg(not(windows))]
+mod system {
+    use super::HGlobal;
+
+    extern "C" {
+        fn malloc(size: usize) -> HGlobal;
+        #[link_name = "free"]
+        fn libc_free(memory: HGlobal);
+    }
+
+    pub unsafe fn alloc(size: usize) -> HGlobal {
+        malloc(size)
+    }
+
+    pub unsafe fn free(memory: HGlobal) {
+        libc_free(memory)
+    }
+}
+
+static ALLOCATOR: Mutex<Allocator> = Mutex::new(Allocator::system());
+
+/// メモリの確保・解放を差し替えます
+pub fn set_allocator(allocator: Allocator) {
+    *ALLOCATOR.lock().unwrap_or_else(|e| e.into_inner()) = allocator;
+}
+
+fn allocator() -> Allocator {
+    *ALLOCATOR.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// loadで起動したゴースト
+struct Ghost {
+    runtime: Runtime,
+    errors: Vec<String>, // 辞書の読み込みで飛ばしたエラー
+}
+
+// ベースウェアがどのスレッドから呼んでも同じゴーストを使う
+static GHOST: Mutex<Option<Ghost>> = Mutex::new(None);
+
+/// パニックで毒された後も使い続けます
+fn ghost() -> MutexGuard<'static, Option<Ghost>> {
+    GHOST.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// パニックを捕まえ、起きたらNoneを返します
+fn guard<T>(f: impl FnOnce() -> T) -> Option<T> {
+    panic::catch_unwind(AssertUnwindSafe(f)).ok()
+}
+
+/// ゴーストのディレクトリから辞書とsavedata.txtを読み込みます
+/// 解析できないブロックは飛ばして起動し、そのエラーは覚えておきます
+fn boot(dir: &str) -> Result<Ghost, String> {
+    let dir = PathBuf::from(dir);
+    let (dictionary, errors) = Dictionary::load_partial(&dir);
+    if dictionary.files.is_empty() {
+        Err(format!("{}: 辞書ファイルがありません", dir.display()))?
+    }
+    let savedata = SaveData::new(dir.join("savedata.txt")).with_interval(AUTOSAVE_INTERVAL);
+    let runtime = Runtime::new(dictionary.satori)
+        .with_savedata(savedata)
+        .map_err(|e| e.to_string())?;
+    Ok(Ghost {
+        runtime,
+        errors: errors.iter().map(|e| e.to_string()).collect(),
+    })
+}
+
+/// リクエストのバイト列に応答します
+fn respond(bytes: &[u8]) -> String {
+    let Some(text) = dictionary::decode(bytes) else {
+        return Response::bad_request("文字コードを判別できません").to_string();
+    };
+    let request = match Request::parse(&text) {
+        Ok(request) => request,
+        Err(e) => return Response::bad_request(e.to_string()).to_string(),
+    };
+    match ghost().as_mut() {
+        Some(ghost) if request.id() == Some(GET_DICTIONARY_ERRORS) => dictionary_errors(ghost),
+        Some(ghost) => ghost.runtime.request(&request),
+        None => Response::internal_server_error("loadされていません"),
+    }
+    .to_string()
+}
+
+/// 辞書の読み込みエラーを返します
+/// Valueはエラーの数で、ErrorLevelとErrorDescriptionに1件ずつ\x01区切りで並べます
+fn dictionary_errors(ghost: &Ghost) -> Response {
+    ghost.errors.iter().fold(
+        Response::ok(ghost.errors.len().to_string()),
+        |response, error| response.with_error(ErrorLevel::Warning, error),
+    )
+}
+
+/// 受け取ったメモリを読み、解放します
+///
+/// # Safety
+/// memoryはAllocatorで確保したlenバイトの領域か、ヌルポインタでなければなりません
+unsafe fn take(memory: HGlobal, len: usize) -> Vec<u8> {
+    if memory.is_null() {
+        return vec![];
+    }
+    let bytes = slice::from_raw_parts(memory as *const u8, len).to_vec();
+    (allocator().free)(memory);
+    bytes
+}
+
+/// ゴーストのディレクトリを受け取って起動します
+///
+/// # Safety
+/// hはAllocatorで確保したlenバイトの領域でなければなりません
+/// hはこの関数の中で解放します
+#[no_mangle]
+pub unsafe extern "C" fn load(h: HGlobal, len: c_long) -> c_int {
+    let bytes = take(h, len.max(0) as usize);
+    guard(|| {
+        let Some(dir) = dictionary::decode(&bytes) else {
+            return FALSE;
+        };
+        match boot(&dir) {
+            Ok(loaded) => {
+                *ghost() = Some(loaded);
+                TRUE
+            }
+            Err(_) => FALSE,
+        }
+    })
+    .unwrap_or(FALSE)
+}
+
+/// 変数を保存して終了します
+#[no_mangle]
+pub extern "C" fn unload() -> c_int {
+    guard(|| match ghost().take() {
+        Some(mut ghost) => match ghost.runtime.unload() {
+            Ok(()) => TRUE,
+            Err(_) => FALSE,
+        },
+        None => FALSE,
+    })
+    .unwrap_or(FALSE)
+}
+
+/// リクエストに応答します
+/// 返すメモリはAllocatorで確保し、その長さをlenに書き込みます
+///
+/// # Safety
+/// hはAllocatorで確保した*lenバイトの領域、lenは書き込めるポインタでなければなりません
+/// hはこの関数の中で解放します
+#[no_mangle]
+pub unsafe extern "C" fn request(h: HGlobal, len: *mut c_long) -> HGlobal {
+    if len.is_null() {
+        // 長さが分からなくても、受け取った領域は解放する
+        take(h, 0);
+        return ptr::null_mut();
+    }
+    let bytes = take(h, (*len).max(0) as usize);
+    let response = guard(|| respond(&bytes)).unwrap_or_else(|| {
+        Response::internal_server_error("SHIORIの内部でパニックが起きました").to_string()
+    });
+
+    let memory = (allocator().alloc)(response.len());
+    if memory.is_null() {
+        *len = 0;
+        return memory;
+    }
+    ptr::copy_nonoverlapping(response.as_ptr(), memory as *mut u8, response.len());
+    *len = response.len() as c_long;
+    memory
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        sync::atomic::{AtomicIsize, Ordering},
+    };
+
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    // 確保したまま解放されていない領域の数
+    static LIVE: AtomicIsize = AtomicIsize::new(0);
+
+    unsafe fn counting_alloc(size: usize) -> HGlobal {
+        LIVE.fetch_add(1, Ordering::SeqCst);
+        (Allocator::system().alloc)(size)
+    }
+
+    unsafe fn counting_free(memory: HGlobal) {
+        LIVE.fetch_sub(1, Ordering::SeqCst);
+        (Allocator::system().free)(memory)
+    }
+
+    /// Allocatorで確保した領域にコピーします（ベースウェアの代わり）
+    unsafe fn global(bytes: &[u8]) -> HGlobal {
+        let memory = (allocator().alloc)(bytes.len());
+        ptr::copy_nonoverlapping(bytes.as_ptr(), memory as *mut u8, bytes.len());
+        memory
+    }
+
+    unsafe fn call(text: &str) -> String {
+        let mut len = text.len() as c_long;
+        let memory = request(global(text.as_bytes()), &mut len);
+        String::from_utf8(take(memory, len as usize)).unwrap()
+    }
+
+    #[test]
+    fn guard_test() {
+        assert_eq!(guard(|| 1), Some(1));
+        assert_eq!(guard(|| -> i32 { panic!("テスト") }), None);
+    }
+
+    #[test]
+    fn request_test() {
+        let dir = temp_dir("native");
+        fs::write(
+            dir.join("dic_boot.txt"),
+            "＊OnBoot\n（R0）、起動しました。\n＊OnClose\n＄終了＝した\n",
+        )
+        .unwrap();
+        // 解析できないブロックは飛ばして起動する
+        fs::write(dir.join("dic_broken.txt"), "＊挨拶[朝\nおはよう\n").unwrap();
+        let path = dir.to_str().unwrap();
+        set_allocator(Allocator {
+            alloc: counting_alloc,
+            free: counting_free,
+        });
+
+        unsafe {
+            assert!(call("GET SHIORI/3.0\r\nID: OnBoot\r\n\r\n").starts_with("SHIORI/3.0 500"));
+            assert_eq!(load(global(path.as_bytes()), path.len() as c_long), TRUE);
+
+            let response = call("GET SHIORI/3.0\r\nID: OnBoot\r\nReference0: さくら\r\n\r\n");
+            assert!(response.starts_with("SHIORI/3.0 200 OK\r\n"));
+            assert!(response.contains("\r\nValue: さくら、起動しました。\\e\r\n"));
+            assert!(call("NOTIFY SHIORI/3.0\r\nID: OnClose\r\n\r\n").starts_with("SHIORI/3.0 204"));
+            assert!(call("GET SHIORI/2.6\r\n\r\n").starts_with("SHIORI/3.0 400"));
+
+            let errors = call("GET SHIORI/3.0\r\nID: GetDictionaryErrors\r\n\r\n");
+            assert!(errors.contains("\r\nValue: 1\r\nErrorLevel: warning\r\n"));
+            assert!(errors.contains("dic_broken.txt"));
+        }
+
+        // 別のスレッドからのリクエストも同じゴーストが答える
+        let response = std::thread::spawn(|| unsafe {
+            call("GET SHIORI/3.0\r\nID: OnBoot\r\nReference0: うにゅう\r\n\r\n")
+        })
+        .join()
+        .unwrap();
+        assert!(response.contains("\r\nValue: うにゅう、起動しました。\\e\r\n"));
+
+        assert_eq!(unload(), TRUE);
+        assert_eq!(unload(), FALSE);
+        assert_eq!(
+            fs::read_to_string(dir.join("savedata.txt")).unwrap(),
+            "＄終了\tした\r\n"
+        );
+
+        let missing = dir.join("missing");
+        let missing = missing.to_str().unwrap();
+        unsafe {
+            assert_eq!(
+                load(global(missing.as_bytes()), missing.len() as c_long),
+                FALSE
+            );
+            // lenがヌルでも受け取った領域は解放する
+            assert!(request(global(b"GET SHIORI/3.0\r\n\r\n"), ptr::null_mut()).is_null());
+        }
+
+        // 受け取った領域も返した領域もすべて解放されている
+        assert_eq!(LIVE.load(Ordering::SeqCst), 0);
+        set_allocator(Allocator::system());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,69 @@
+//! トーク・単語の選択に使う乱数
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 乱数生成器
+/// テストでは種を固定したRandomを渡すと結果が決まります
+pub trait Rng {
+    fn next_u64(&mut self) -> u64;
+
+    /// 0以上n未満の値を返します
+    fn below(&mut self, n: usize) -> usize {
+        if n == 0 {
+            return 0;
+        }
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// xorshift64*による乱数生成器
+#[derive(Debug, Clone)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64で種を散らし、0にならないようにする
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Self {
+            state: (z ^ (z >> 31)).max(1),
+        }
+    }
+
+    /// 現在時刻を種にします
+    pub fn from_time() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        Self::new(seed)
+    }
+}
+
+impl Rng for Random {
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seed_test() {
+        let mut a = Random::new(1);
+        let mut b = Random::new(1);
+        let values = (0..10).map(|_| a.below(6)).collect::<Vec<_>>();
+
+        assert_eq!(values, (0..10).map(|_| b.below(6)).collect::<Vec<_>>());
+        assert!(values.iter().all(|v| *v < 6));
+        assert_eq!(Random::new(0).below(0), 0);
+    }
+}
@@ -0,0 +1,1098 @@
+//! 解析済みの辞書を実行してさくらスクリプトを生成します
+
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use parser::{
+    ast,
+    token::{Content, Macro, Primitives, VariableDeclaration},
+    Number,
+};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    eval,
+    random::{Random, Rng},
+    savedata::{SaveData, SaveError},
+    selector::{Policy, TalkSelector},
+    text::RegexCache,
+    time,
+    variable::{Value, Variables},
+    vocabulary::{Vocabulary, Word},
+    word::{WordPicker, WordPolicy},
+};
+
+mod event;
+mod function;
+
+pub use event::Hook;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    TalkNotFound(String), // 指定された名前のトークがない（条件をすべて満たさない場合を含む）
+    RandomTalkNotFound,   // 条件を満たすランダムトークがない
+    Evaluation(String),   // 式を評価できない
+    LimitExceeded {
+        limit: Limit,       // 超えた上限
+        chain: Vec<String>, // 上限を超えたときの呼び出し中のラベル（外側から順）
+    },
+}
+
+/// 展開の上限の種類
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    Depth(usize),      // 呼び出しの深さ
+    Expansions(usize), // 1回のリクエストでの展開回数
+    Output(usize),     // 出力の長さ（バイト数）
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Depth(n) => write!(f, "呼び出しの深さ（{}）", n),
+            Limit::Expansions(n) => write!(f, "展開回数（{}）", n),
+            Limit::Output(n) => write!(f, "出力の長さ（{}バイト）", n),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::TalkNotFound(label) => write!(f, "トークが見つかりません: {}", label),
+            Error::RandomTalkNotFound => write!(f, "ランダムトークが見つかりません"),
+            Error::Evaluation(message) => write!(f, "式を評価できません: {}", message),
+            Error::LimitExceeded { limit, chain } => {
+                write!(f, "{}の上限を超えました: {}", limit, chain.join(" → "))
+            }
+        }
+    }
+}
+
+/// 里々の実行環境
+pub struct Runtime {
+    satori: Arc<ast::Satori>,
+    talks: HashMap<String, Vec<usize>>, // トーク名 → 添字
+    random_talks: Vec<usize>,           // 無名のトークの添字
+    vocabulary: Vocabulary,
+    selector: TalkSelector,
+    words: WordPicker,
+    random: Box<dyn Rng + Send>,
+    clock: Box<dyn Clock + Send>,
+    booted: i64, // ゴーストが起動したときのUnix時間
+    script: ScriptConfig,
+    variables: Variables,
+    savedata: Option<SaveData>,
+    limits: Limits,
+    regexes: RegexCache,
+    hooks: Vec<Box<dyn Hook + Send>>, // リクエストの前後に呼ぶ処理
+}
+
+/// さくらスクリプトを出力するときの設定
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptConfig {
+    pub line_break: String,  // 同じスコープ内の改行
+    pub scope_break: String, // 一度話したスコープに戻ったときの改行（里々の「スコープ切り換え時の改行」）
+}
+
+impl Default for ScriptConfig {
+    fn default() -> Self {
+        Self {
+            line_break: "\\n".to_string(),
+            scope_break: "\\n[half]".to_string(),
+        }
+    }
+}
+
+/// 自己参照する単語群やジャンプの無限ループを止めるための上限
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub depth: usize,      // 呼び出しの深さ
+    pub expansions: usize, // 1回のリクエストでの展開回数
+    pub output: usize,     // 出力の長さ（バイト数）
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            depth: 100,
+            expansions: 10_000,
+            output: 1 << 20,
+        }
+    }
+}
+
+/// 1回のトーク生成中の状態
+#[derive(Debug, Default)]
+struct Context {
+    scope: usize,            // 現在のスコープ（0: \0、1: \1）
+    spoken: [bool; 2],       // スコープごとに何か出力したか
+    chain: Vec<String>,      // 呼び出し中のラベル（外側から順）
+    expansions: usize,       // これまでの展開回数
+    arguments: Vec<String>,  // callで渡された引数（A0、A1…）
+    references: Vec<String>, // リクエストのReference（R0、R1…）
+    captures: Vec<String>,   // reg_match・reg_splitの結果（S0、S1…）
+}
+
+impl Runtime {
+    pub fn new(satori: ast::Satori) -> Self {
+        let mut talks: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut random_talks = vec![];
+        for (idx, talk) in satori.talk.iter().enumerate() {
+            match talk.start.name() {
+                Some(name) => talks.entry(name.to_string()).or_default().push(idx),
+                None => random_talks.push(idx),
+            }
+        }
+
+        Self {
+            vocabulary: Vocabulary::new(&satori),
+            satori: Arc::new(satori),
+            talks,
+            random_talks,
+            selector: TalkSelector::default(),
+            words: WordPicker::default(),
+            random: Box::new(Random::from_time()),
+            clock: Box::new(SystemClock),
+            booted: SystemClock.unix_time(),
+            script: ScriptConfig::default(),
+            variables: Variables::new(),
+            savedata: None,
+            limits: Limits::default(),
+            regexes: RegexCache::new(),
+            hooks: vec![],
+        }
+    }
+
+    /// 展開の上限を変更します
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// 改行の出力を変更します
+    pub fn with_script(mut self, script: ScriptConfig) -> Self {
+        self.script = script;
+        self
+    }
+
+    /// トークの重複回避の方針を変更します
+    pub fn with_policy(mut self, policy: Policy) -> Self {
+        self.selector = TalkSelector::new(policy);
+        self
+    }
+
+    pub fn selector(&self) -> &TalkSelector {
+        &self.selector
+    }
+
+    /// 単語の選び方を変更します
+    pub fn with_word_policy(mut self, policy: WordPolicy) -> Self {
+        self.words = WordPicker::new(policy);
+        self
+    }
+
+    /// 乱数を差し替えます
+    pub fn with_random(mut self, random: impl Rng + Send + 'static) -> Self {
+        self.random = Box::new(random);
+        self
+    }
+
+    /// 時計を差し替えます
+    /// 起動時刻も差し替えた時計の現在時刻になります
+    pub fn with_clock(mut self, clock: impl Clock + Send + 'static) -> Self {
+        self.booted = clock.unix_time();
+        self.clock = Box::new(clock);
+        self
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    /// ゴーストが起動してからの秒数
+    pub fn elapsed(&self) -> i64 {
+        self.clock.unix_time() - self.booted
+    }
+
+    pub fn variables(&self) -> &Variables {
+        &self.variables
+    }
+
+    /// ホストから変数を読み書きするときに使います
+    pub fn variables_mut(&mut self) -> &mut Variables {
+        &mut self.variables
+    }
+
+    pub fn vocabulary(&self) -> &Vocabulary {
+        &self.vocabulary
+    }
+
+    /// savedata.txtから変数と単語群への変更を読み込み、以後の保存先にします
+    pub fn with_savedata(mut self, mut savedata: SaveData) -> Result<Self, SaveError> {
+        let (variables, learned) = savedata.load()?;
+        self.variables = variables;
+        self.vocabulary.restore(&learned);
+        self.savedata = Some(savedata);
+        Ok(self)
+    }
+
+    /// 終了時に変数と単語群への変更を保存します
+    pub fn unload(&mut self) -> Result<(), SaveError> {
+        match &mut self.savedata {
+            Some(savedata) => savedata.save(&self.variables, self.vocabulary.learned()),
+            None => Ok(()),
+        }
+    }
+
+    /// 自動保存の間隔が過ぎていれば変数と単語群への変更を保存します
+    pub fn autosave(&mut self) -> Result<bool, SaveError> {
+        match &mut self.savedata {
+            Some(savedata) => savedata.save_if_due(&self.variables, self.vocabulary.learned()),
+            None => Ok(false),
+        }
+    }
+
+    /// 指定したトークを実行してさくらスクリプトを返します
+    /// 同じ名前のトークが複数あれば、条件を満たすものから1つを選びます
+    pub fn talk(&mut self, label: &str) -> Result<String, Error> {
+        self.talk_with(label, vec![])
+    }
+
+    /// Reference0、Reference1…を（R0）、（R1）…として渡してトークを実行します
+    /// 渡したReferenceはこのトークの中でだけ参照できます
+    pub fn talk_with(&mut self, label: &str, references: Vec<String>) -> Result<String, Error> {
+        let mut ctx = Context {
+            chain: vec![label.to_string()],
+            references,
+            ..Context::default()
+        };
+        let idx = self
+            .choose_talk(label, &mut ctx)?
+            .ok_or_else(|| Error::TalkNotFound(label.to_string()))?;
+        self.run(idx, ctx)
+    }
+
+    /// 無名のトークから条件を満たすものを1つ選んで実行します
+    pub fn random_talk(&mut self) -> Result<String, Error> {
+        let mut ctx = Context {
+            chain: vec!["＊".to_string()],
+            ..Context::default()
+        };
+        let candidates = self.available(&self.random_talks.clone(), &mut ctx)?;
+        let idx = self
+            .selector
+            .choose(None, &self.random_talks, &candidates, &mut *self.random)
+            .ok_or(Error::RandomTalkNotFound)?;
+        self.run(idx, ctx)
+    }
+
+    fn run(&mut self, idx: usize, mut ctx: Context) -> Result<String, Error> {
+        let mut script = self.render_talk(idx, &mut ctx)?;
+        script.push_str("\\e");
+        Ok(script)
+    }
+
+    /// 名前付きのトークから条件を満たすものを1つ選びます
+    fn choose_talk(&mut self, label: &str, ctx: &mut Context) -> Result<Option<usize>, Error> {
+        let Some(indices) = self.talks.get(label).cloned() else {
+            return Ok(None);
+        };
+        let candidates = self.available(&indices, ctx)?;
+        Ok(self
+            .selector
+            .choose(Some(label), &indices, &candidates, &mut *self.random))
+    }
+
+    /// 見出しの条件を満たすトークだけを返します
+    fn available(&mut self, indices: &[usize], ctx: &mut Context) -> Result<Vec<usize>, Error> {
+        let satori = self.satori.clone();
+        let mut candidates = vec![];
+        for idx in indices {
+            let holds = match &satori.talk[*idx].start.condition {
+                Some(condition) => self.holds(condition, ctx)?,
+                None => true,
+            };
+            if holds {
+                candidates.push(*idx);
+            }
+        }
+        Ok(candidates)
+    }
+
+    /// 単語群のうち、見出しの条件を満たす＠ブロックの単語だけを返します
+    fn available_words(&mut self, name: &str, ctx: &mut Context) -> Result<Vec<Word>, Error> {
+        let conditions = self
+            .vocabulary
+            .blocks(name)
+            .iter()
+            .map(|block| block.condition.clone())
+            .collect::<Vec<_>>();
+        let mut words = vec![];
+        for (n, condition) in conditions.iter().enumerate() {
+            let holds = match condition {
+                Some(condition) => self.holds(condition, ctx)?,
+                None => true,
+            };
+            if holds {
+                words.extend_from_slice(&self.vocabulary.blocks(name)[n].words);
+            }
+        }
+        Ok(words)
+    }
+
+    /// 条件式が真か判定します
+    fn holds(&mut self, condition: &ast::Expression, ctx: &mut Context) -> Result<bool, Error> {
+        // 条件式の中の展開では出力中のスコープを変えない
+        let (scope, spoken) = (ctx.scope, ctx.spoken);
+        let value = eval::evaluate(condition, &mut |name| self.call(name, ctx))?;
+        (ctx.scope, ctx.spoken) = (scope, spoken);
+        Ok(eval::is_true(&value))
+    }
+
+    /// 呼び出しを記録し、上限を超えていればエラーを返します
+    fn enter(&self, label: &str, ctx: &mut Context) -> Result<(), Error> {
+        ctx.chain.push(label.to_string());
+        if ctx.chain.len() > self.limits.depth {
+            Err(self.exceeded(Limit::Depth(self.limits.depth), ctx))?
+        }
+        self.count(ctx)
+    }
+
+    /// 展開回数を数え、上限を超えていればエラーを返します
+    fn count(&self, ctx: &mut Context) -> Result<(), Error> {
+        ctx.expansions += 1;
+        if ctx.expansions > self.limits.expansions {
+            Err(self.exceeded(Limit::Expansions(self.limits.expansions), ctx))?
+        }
+        Ok(())
+    }
+
+    /// 出力が上限を超えていればエラーを返します
+    fn check_output(&self, script: &str, ctx: &Context) -> Result<(), Error> {
+        if script.len() > self.limits.output {
+            Err(self.exceeded(Limit::Output(self.limits.output), ctx))?
+        }
+        Ok(())
+    }
+
+    fn exceeded(&self, limit: Limit, ctx: &Context) -> Error {
+        Error::LimitExceeded {
+            limit,
+            chain: ctx.chain.clone(),
+        }
+    }
+
+    /// トークの全行を出力します
+    /// ：で始まる行は\\0と\\1を切り替え、それ以外の行は改行でつなげます
+    /// ジャンプした場合はそこまでの出力に続けてジャンプ先の行を出力します
+    fn render_talk(&mut self, idx: usize, ctx: &mut Context) -> Result<String, Error> {
+        let satori = self.satori.clone();
+        let mut script = String::new();
+        let mut first = true;
+        let depth = ctx.chain.len();
+        let mut lines = satori.talk[idx].contents.iter();
+        while let Some(line) = lines.next() {
+            if is_jump(line) {
+                if let Some(target) = self.jump(line, ctx)? {
+                    // ジャンプの繰り返しも呼び出しとして数える
+                    self.enter(satori.talk[target].start.name().unwrap_or("＊"), ctx)?;
+                    lines = satori.talk[target].contents.iter();
+                }
+                continue;
+            }
+            if let Some(Content::VariableDeclaration(VariableDeclaration::Name(name))) =
+                line.first()
+            {
+                self.assign(name, &line[1..], ctx)?;
+                continue;
+            }
+            match line.first() {
+                Some(Content::ScopeChange(_)) => {
+                    ctx.scope = 1 - ctx.scope;
+                    script.push_str(&format!("\\{}", ctx.scope));
+                    if ctx.spoken[ctx.scope] {
+                        script.push_str(&self.script.scope_break);
+                    }
+                }
+                _ if !first => script.push_str(&self.script.line_break),
+                _ => (),
+            }
+            first = false;
+            script.push_str(&self.render_line(line, ctx)?);
+            self.check_output(&script, ctx)?;
+            ctx.spoken[ctx.scope] = true;
+        }
+        ctx.chain.truncate(depth);
+        Ok(script)
+    }
+
+    /// ジャンプ先のトークを選びます
+    /// 行の条件が偽の場合や、条件を満たすジャンプ先がない場合はNoneを返します
+    fn jump(&mut self, line: &[Content], ctx: &mut Context) -> Result<Option<usize>, Error> {
+        if let Some(Content::Condition(condition)) = line.get(1) {
+            if !self.holds(condition, ctx)? {
+                return Ok(None);
+            }
+        }
+
+        let satori = self.satori.clone();
+        let (key, indices) = match line.first() {
+            Some(Content::Jump(label)) => return self.choose_talk(label, ctx),
+            // ≫ トーク名の一部が一致するトーク
+            Some(Content::AmbiguousSearchJump(text)) => (
+                format!("≫{}", text),
+                satori
+                    .talk
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, talk)| {
+                        talk.start
+                            .name()
+                            .is_some_and(|name| name.contains(text.as_str()))
+                    })
+                    .map(|(idx, _)| idx)
+                    .collect::<Vec<_>>(),
+            ),
+            // ≧ タグが一致するトーク
+            Some(Content::TagAmbiguousSearchJump(text)) => (
+                format!("≧{}", text),
+                satori
+                    .talk
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, talk)| talk.start.has_tag(text))
+                    .map(|(idx, _)| idx)
+                    .collect::<Vec<_>>(),
+            ),
+            _ => return Ok(None),
+        };
+        // 選び方は＞と同じく選択方針に従う（履歴は≫・≧の検索語ごと）
+        let candidates = self.available(&indices, ctx)?;
+        Ok(self
+            .selector
+            .choose(Some(&key), &indices, &candidates, &mut *self.random))
+    }
+
+    /// ＄行の値を変数に代入します
+    /// 代入行は何も出力しません
+    fn assign(&mut self, name: &str, value: &[Content], ctx: &mut Context) -> Result<(), Error> {
+        let value = match value {
+            // 辞書に直接書かれた計算式（＄回数＝１＋１）は計算する
+            [Content::VariableDeclaration(VariableDeclaration::Value(Primitives::String(s)))] => {
+                match eval::calculate(s) {
+                    Some(result) => result?.into(),
+                    None => s.clone().into(),
+                }
+            }
+            [Content::VariableDeclaration(VariableDeclaration::Value(primitive))] => {
+                Value::from(primitive)
+            }
+            _ => {
+                // 辞書に書かれたとおりの式を計算する
+                // 展開した値の中の記号（日付の／など）は演算子とみなさない
+                let mut rendered = vec![];
+                for content in value {
+                    let part = self.render_line(std::slice::from_ref(content), ctx)?;
+                    rendered.push((matches!(content, Content::Macro(_)), part));
+                }
+                let parts = rendered
+                    .iter()
+                    .map(|(expanded, part)| {
+                        if *expanded {
+                            eval::Part::Expanded(part)
+                        } else {
+                            eval::Part::Text(part)
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                match eval::calculate_parts(&parts) {
+                    Some(result) => result?.into(),
+                    None => rendered
+                        .iter()
+                        .map(|(_, part)| part.as_str())
+                        .collect::<String>()
+                        .into(),
+                }
+            }
+        };
+        self.variables.set(name, value);
+        Ok(())
+    }
+
+    /// 1行分の文とマクロ展開式を出力します
+    fn render_line(&mut self, line: &[Content], ctx: &mut Context) -> Result<String, Error> {
+        let mut text = String::new();
+        for content in line {
+            match content {
+                Content::Sentense(s) => text.push_str(s),
+                Content::Macro(m) => text.push_str(&self.expand(m, ctx)?),
+                _ => (),
+            }
+            self.check_output(&text, ctx)?;
+        }
+        Ok(text)
+    }
+
+    /// （…）を展開します
+    fn expand(&mut self, m: &Macro, ctx: &mut Context) -> Result<String, Error> {
+        match m {
+            Macro::TalkCalling(name) => self.call(name, ctx),
+            Macro::Macro(inner) => {
+                let name = self.expand(inner, ctx)?;
+                self.call(&name, ctx)
+            }
+            Macro::FunctionCall(call) => self.call_function(call, ctx),
+            Macro::SurfaceChange(surface) => Ok(format!(
+                "\\s[{}]",
+                Number::parse(surface).map_or(surface.clone(), |n| n.to_string())
+            )),
+            Macro::VariableExpansion(names) => Ok(self.variables.expand(&names.concat())),
+        }
+    }
+
+    /// 名前を引数、Reference、変数、システム変数、単語群、トークの順に探して展開します
+    /// 見つからなければ空文字列になります
+    fn call(&mut self, name: &str, ctx: &mut Context) -> Result<String, Error> {
+        if let Some(value) = argument(name, ctx) {
+            return Ok(value.to_string());
+        }
+        if let Some(value) = reference(name, ctx) {
+            return Ok(value);
+        }
+        if let Some(value) = capture(name, ctx) {
+            return Ok(value.to_string());
+        }
+        if let Some(value) = self.variables.get(name) {
+            return Ok(value.to_string());
+        }
+        if let Some(value) = time::variable(name, self.clock.as_ref(), self.booted) {
+            return Ok(value);
+        }
+
+        if !self.vocabulary.contains(name) && !self.talks.contains_key(name) {
+            return Ok(String::new());
+        }
+
+        self.enter(name, ctx)?;
+        let text = if self.vocabulary.contains(name) {
+            let words = self.available_words(name, ctx)?;
+            match self.words.pick(name, words.len(), &mut *self.random) {
+                Some(i) => match words[i].clone() {
+                    Word::Dictionary(line) => self.render_line(&line, ctx)?,
+                    Word::Learned(text) => text,
+                },
+                None => String::new(),
+            }
+        } else {
+            match self.choose_talk(name, ctx)? {
+                Some(idx) => self.render_talk(idx, ctx)?,
+                None => String::new(),
+            }
+        };
+        ctx.chain.pop();
+        Ok(text)
+    }
+}
+
+/// callの引数（A0、A1…）を返します
+fn argument<'a>(name: &str, ctx: &'a Context) -> Option<&'a str> {
+    let n = index(name, 'A')?;
+    ctx.arguments.get(n).map(|arg| arg.as_str())
+}
+
+/// リクエストのReference（R0、R1…）とその数（Rの数）を返します
+fn reference(name: &str, ctx: &Context) -> Option<String> {
+    if name == "Rの数" {
+        return Some(ctx.references.len().to_string());
+    }
+    let n = index(name, 'R')?;
+    ctx.references.get(n).cloned()
+}
+
+/// reg_match・reg_splitの結果（S0、S1…）を返します
+fn capture<'a>(name: &str, ctx: &'a Context) -> Option<&'a str> {
+    let n = index(name, 'S')?;
+    ctx.captures.get(n).map(|capture| capture.as_str())
+}
+
+/// A0・R12・S3のような名前の添字を返します
+/// 符号や先頭の0がつくもの（R+0、R00など）は添字とみなしません
+fn index(name: &str, prefix: char) -> Option<usize> {
+    let digits = name.strip_prefix(prefix)?;
+    if digits.is_empty()
+        || !digits.bytes().all(|b| b.is_ascii_digit())
+        || (digits.len() > 1 && digits.starts_with('0'))
+    {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// ＞・≫・≧の行か判定します
+fn is_jump(line: &[Content]) -> bool {
+    matches!(
+        line.first(),
+        Some(
+            Content::Jump(_) | Content::AmbiguousSearchJump(_) | Content::TagAmbiguousSearchJump(_)
+        )
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        clock::FakeClock,
+        test_util::{runtime, temp_dir},
+    };
+
+    #[test]
+    fn sentence_test() {
+        let mut runtime = runtime("＊OnBoot\nこんにちは。\nいい天気ですね。\n");
+
+        assert_eq!(
+            runtime.talk("OnBoot").unwrap(),
+            "こんにちは。\\nいい天気ですね。\\e"
+        );
+    }
+
+    #[test]
+    fn scope_test() {
+        let mut runtime = runtime("＊OnBoot\nねえ。\n：なに？\n：別に。\n");
+
+        assert_eq!(
+            runtime.talk("OnBoot").unwrap(),
+            "ねえ。\\1なに？\\0\\n[half]別に。\\e"
+        );
+    }
+
+    #[test]
+    fn script_test() {
+        let mut dialogue = runtime(
+            "＊OnBoot\n：（１０）やあ。\nどうも。\n：（０）こんにちは。\n：元気？\n\n：うん。\n",
+        );
+
+        assert_eq!(
+            dialogue.talk("OnBoot").unwrap(),
+            "\\1\\s[10]やあ。\\nどうも。\\0\\s[0]こんにちは。\\1\\n[half]元気？\\n\\0\\n[half]うん。\\e"
+        );
+
+        let mut plain =
+            runtime("＊OnBoot\nねえ。\n：なに？\n：別に。\n").with_script(ScriptConfig {
+                line_break: "\\n".to_string(),
+                scope_break: "\\n\\n".to_string(),
+            });
+        assert_eq!(
+            plain.talk("OnBoot").unwrap(),
+            "ねえ。\\1なに？\\0\\n\\n別に。\\e"
+        );
+    }
+
+    #[test]
+    fn expand_test() {
+        let mut runtime = runtime(
+            r"
+            ＊OnBoot
+            今日は（天気）ですね。（締め）
+            ＊締め
+            また明日。
+            ＠天気
+            （晴れ）
+            ＠晴れ
+            快晴
+            ",
+        );
+
+        assert_eq!(
+            runtime.talk("OnBoot").unwrap(),
+            "今日は快晴ですね。また明日。\\e"
+        );
+    }
+
+    #[test]
+    fn random_word_test() {
+        let mut runtime = runtime("＊OnBoot\n（天気）\n＠天気\n晴れ\n雨\n");
+
+        let scripts = (0..20)
+            .map(|_| runtime.talk("OnBoot").unwrap())
+            .collect::<Vec<_>>();
+        assert!(scripts.contains(&"晴れ\\e".to_string()));
+        assert!(scripts.contains(&"雨\\e".to_string()));
+    }
+
+    #[test]
+    fn word_no_repeat_test() {
+        let mut shuffled = runtime("＊OnBoot\n（天気）\n＠天気\n晴れ\n（雨）\n＠雨\n小雨\n");
+
+        let scripts = (0..10)
+            .map(|_| shuffled.talk("OnBoot").unwrap())
+            .collect::<Vec<_>>();
+        assert!(scripts.windows(2).all(|w| w[0] != w[1]));
+        assert!(scripts.contains(&"小雨\\e".to_string()));
+
+        let mut sequential = runtime("＊OnBoot\n（天気）\n＠天気\n晴れ\n雨\n")
+            .with_word_policy(WordPolicy::Sequential);
+        assert_eq!(sequential.talk("OnBoot").unwrap(), "晴れ\\e");
+        assert_eq!(sequential.talk("OnBoot").unwrap(), "雨\\e");
+    }
+
+    #[test]
+    fn word_condition_test() {
+        let mut seasonal = runtime(
+            "＊OnBoot\n（天気）\n＠天気\t（季節）＝＝冬\n雪\n＠天気\t（季節）＝＝夏\n夕立\n＠天気\n晴れ\n",
+        );
+        seasonal.variables_mut().set("季節", "夏");
+
+        let scripts = (0..20)
+            .map(|_| seasonal.talk("OnBoot").unwrap())
+            .collect::<Vec<_>>();
+        assert!(!scripts.contains(&"雪\\e".to_string()));
+        assert!(scripts.contains(&"夕立\\e".to_string()));
+        assert!(scripts.contains(&"晴れ\\e".to_string()));
+    }
+
+    #[test]
+    fn variable_test() {
+        let mut runtime = runtime(
+            "＊OnBoot\n＄回数＝１０\n＄名前\t（呼び名）さん\n（名前）、（回数）回目です。（未定義）\n＊OnClose\t（回数）＝＝10\n（名前）\n＠呼び名\nさくら\n",
+        );
+
+        assert_eq!(
+            runtime.talk("OnBoot").unwrap(),
+            "さくらさん、１０回目です。\\e"
+        );
+        assert_eq!(runtime.talk("OnClose").unwrap(), "さくらさん\\e");
+        assert_eq!(runtime.variables().expand("回数"), "１０");
+
+        runtime.variables_mut().set("回数", "11");
+        assert_eq!(
+            runtime.talk("OnClose"),
+            Err(Error::TalkNotFound("OnClose".to_string()))
+        );
+        assert_eq!(
+            runtime
+                .variables()
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>(),
+            vec!["名前=さくらさん", "回数=11"]
+        );
+    }
+
+    #[test]
+    fn assign_test() {
+        let mut runtime =
+            runtime("＊OnBoot\n＄回数＝（回数）＋１\n＄名前＝さくら＋うにゅう\n（回数）回目\n");
+
+        assert_eq!(runtime.talk("OnBoot").unwrap(), "1回目\\e");
+        assert_eq!(runtime.talk("OnBoot").unwrap(), "2回目\\e");
+        assert_eq!(runtime.variables().expand("名前"), "さくら＋うにゅう");
+    }
+
+    #[test]
+    fn assign_formula_test() {
+        let mut runtime = runtime(
+            "＊OnBoot\n＄コピー＝（日付）\n＄電話＝（番号）\n＄y＝１＋１\n＄z＝（x）＋（x）\n＄w＝（日付）＋１\n",
+        );
+        runtime.variables_mut().set("日付", "２０２４／４／１");
+        runtime.variables_mut().set("番号", "03－1234");
+        runtime.variables_mut().set("x", "3");
+        runtime.talk("OnBoot").unwrap();
+
+        // 展開した値の中の／や－は計算しない
+        assert_eq!(runtime.variables().expand("コピー"), "２０２４／４／１");
+        assert_eq!(runtime.variables().expand("電話"), "03－1234");
+        assert_eq!(runtime.variables().expand("w"), "２０２４／４／１＋１");
+        // 辞書に書かれた式は計算する
+        assert_eq!(runtime.variables().expand("y"), "2");
+        assert_eq!(runtime.variables().expand("z"), "6");
+    }
+
+    #[test]
+    fn jump_test() {
+        let mut runtime = runtime(
+            r"
+            ＊OnBoot
+            こんにちは。
+            ＞挨拶	（時間）＝＝夜
+            ＞挨拶
+            ここは出力されない
+            ＊挨拶	（時間）＝＝夜
+            こんばんは。
+            ＊挨拶
+            ：やあ。
+            ＊OnClose
+            ≫別れの
+            ＊別れの挨拶
+            さようなら。
+            ＊OnSecondChange
+            ≧季節
+            ＊雑談[季節、春]
+            桜の季節だね。
+            ＊雑談[季節外れ]
+            雪だね。
+            ",
+        );
+
+        assert_eq!(runtime.talk("OnBoot").unwrap(), "こんにちは。\\1やあ。\\e");
+        runtime.variables_mut().set("時間", "夜");
+        assert_eq!(
+            runtime.talk("OnBoot").unwrap(),
+            "こんにちは。\\nこんばんは。\\e"
+        );
+        assert_eq!(runtime.talk("OnClose").unwrap(), "さようなら。\\e");
+        assert_eq!(runtime.talk("OnSecondChange").unwrap(), "桜の季節だね。\\e");
+    }
+
+    #[test]
+    fn limit_test() {
+        let mut recursive = runtime("＊OnBoot\n（天気）\n＠天気\n（季節）\n＠季節\n（天気）\n");
+        let Err(Error::LimitExceeded { limit, chain }) = recursive.talk("OnBoot") else {
+            panic!("上限を超えるはず");
+        };
+        assert_eq!(limit, Limit::Depth(100));
+        assert_eq!(chain.len(), 101);
+        assert_eq!(&chain[..4], ["OnBoot", "天気", "季節", "天気"]);
+
+        let mut jump = runtime("＊OnBoot\nループ\n＞OnBoot\n");
+        assert!(matches!(
+            jump.talk("OnBoot"),
+            Err(Error::LimitExceeded {
+                limit: Limit::Depth(100),
+                ..
+            })
+        ));
+
+        let mut breadth =
+            runtime("＊OnBoot\n（a）\n＠a\n（b）（b）（b）\n＠b\n（c）（c）（c）\n＠c\nあ\n")
+                .with_limits(Limits {
+                    expansions: 10,
+                    ..Limits::default()
+                });
+        assert_eq!(
+            breadth.talk("OnBoot"),
+            Err(Error::LimitExceeded {
+                limit: Limit::Expansions(10),
+                chain: vec![
+                    "OnBoot".to_string(),
+                    "a".to_string(),
+                    "b".to_string(),
+                    "c".to_string()
+                ],
+            })
+        );
+
+        let mut long = runtime("＊OnBoot\nこんにちは\n").with_limits(Limits {
+            output: 8,
+            ..Limits::default()
+        });
+        assert_eq!(
+            long.talk("OnBoot").unwrap_err().to_string(),
+            "出力の長さ（8バイト）の上限を超えました: OnBoot"
+        );
+    }
+
+    #[test]
+    fn savedata_test() {
+        let dir = temp_dir("runtime-savedata");
+        let path = dir.join("savedata.txt");
+        std::fs::write(&path, "＄回数\t1\r\n").unwrap();
+
+        let mut first = runtime("＊OnBoot\n＄回数＝2\n（回数）\n")
+            .with_savedata(SaveData::new(&path))
+            .unwrap();
+        assert_eq!(first.variables().expand("回数"), "1");
+        assert_eq!(first.talk("OnBoot").unwrap(), "2\\e");
+        assert!(!first.autosave().unwrap());
+        first.unload().unwrap();
+
+        let second = runtime("").with_savedata(SaveData::new(&path)).unwrap();
+        assert_eq!(second.variables().expand("回数"), "2");
+
+        // 覚えた単語も次の起動に引き継ぐ
+        let src =
+            "＊OnBoot\n（単語の追加、果物、ぶどう）（単語の削除、果物、りんご）\n＠果物\nりんご\n";
+        let mut third = runtime(src).with_savedata(SaveData::new(&path)).unwrap();
+        third.talk("OnBoot").unwrap();
+        third.unload().unwrap();
+
+        let mut fourth = runtime(src).with_savedata(SaveData::new(&path)).unwrap();
+        assert_eq!(fourth.vocabulary().count("果物"), 1);
+        assert_eq!(
+            fourth.vocabulary().words("果物").next().unwrap().text(),
+            Some("ぶどう")
+        );
+        fourth.talk("OnBoot").unwrap();
+        assert_eq!(fourth.vocabulary().count("果物"), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn deterministic_test() {
+        let src = "＊\n（天気）\n＊\nおはよう\n＊\nこんにちは\n＠天気\n晴れ\n雨\n曇り\n";
+        let talks = |seed| {
+            let mut runtime =
+                Runtime::new(parser::block::parse(src, 0).unwrap()).with_random(Random::new(seed));
+            (0..10)
+                .map(|_| runtime.random_talk().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(talks(7), talks(7));
+
+        let clock = FakeClock::at(2024, 4, 1, 7, 0, 0).unwrap();
+        let runtime = runtime("").with_clock(clock.clone());
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(runtime.elapsed(), 60);
+    }
+
+    #[test]
+    fn time_test() {
+        let clock = FakeClock::at(2024, 4, 1, 7, 0, 0).unwrap();
+        let mut runtime = runtime(
+            r"
+            ＊OnBoot	（現在曜日）＝＝1
+            （挨拶）
+            ＊OnBoot	（現在曜日）＝＝0
+            日曜日だね。
+            ＠挨拶
+            おはようございます。
+            ",
+        )
+        .with_clock(clock.clone());
+
+        assert_eq!(runtime.talk("OnBoot").unwrap(), "おはようございます。\\e");
+        clock.advance(Duration::from_secs(6 * 24 * 3600));
+        assert_eq!(runtime.talk("OnBoot").unwrap(), "日曜日だね。\\e");
+    }
+
+    #[test]
+    fn random_talk_test() {
+        let mut runtime = runtime(
+            "＊\nおはよう\n＊\nこんにちは\n＊\tさくら＝＝うにゅう\nこない\n＊OnBoot\n起動\n",
+        );
+
+        for _ in 0..5 {
+            let mut scripts = (0..2)
+                .map(|_| runtime.random_talk().unwrap())
+                .collect::<Vec<_>>();
+            scripts.sort();
+            assert_eq!(scripts, vec!["おはよう\\e", "こんにちは\\e"]);
+        }
+        assert_eq!(runtime.selector().history(None).len(), 2);
+    }
+
+    #[test]
+    fn condition_test() {
+        let mut morning = runtime(
+            "＊OnBoot\t（モード）＝＝夜\nこんばんは\n＊OnBoot\t（モード）＝＝朝\nおはよう\n＠モード\n朝\n",
+        );
+
+        for _ in 0..3 {
+            assert_eq!(morning.talk("OnBoot").unwrap(), "おはよう\\e");
+        }
+
+        let mut other = runtime("＊OnBoot\tさくら＝＝うにゅう\nこない\n＊\t0\nこない\n");
+        assert_eq!(
+            other.talk("OnBoot"),
+            Err(Error::TalkNotFound("OnBoot".to_string()))
+        );
+        assert_eq!(other.random_talk(), Err(Error::RandomTalkNotFound));
+
+        // ＋－を含む条件も＝＝の前に計算する
+        let mut count = runtime(
+            "＊OnBoot	（回数）－１＝＝５
+五回目
+＊OnBoot	（回数）－１＝＝９
+十回目
+",
+        );
+        count.variables_mut().set("回数", "10");
+        assert_eq!(count.talk("OnBoot").unwrap(), "十回目\\e");
+    }
+
+    #[test]
+    fn comparison_condition_test() {
+        let conditions = [
+            ("（現在時）＜１２", true),
+            ("（現在時）＜７", false),
+            ("（現在時）＜＝７", true),
+            ("（現在時）≦６", false),
+            ("（現在時）＞６", true),
+            ("（現在時）＞７", false),
+            ("（現在時）＞＝７", true),
+            ("（現在時）≧８", false),
+            ("（回数）！＝１", true),
+            ("（回数）！＝２", false),
+            ("（名前）＝＝さくら。", true),
+            ("（名前） ＝＝ さくら", false),
+            ("（現在時） ＝＝ ７", true),
+        ];
+        for (condition, holds) in conditions {
+            let mut runtime = runtime(&format!("＊挨拶\t{}\n成立\n", condition))
+                .with_clock(FakeClock::at(2024, 4, 1, 7, 0, 0).unwrap());
+            runtime.variables_mut().set("回数", "2");
+            runtime.variables_mut().set("名前", "さくら。");
+            assert_eq!(runtime.talk("挨拶").is_ok(), holds, "{}", condition);
+        }
+    }
+
+    #[test]
+    fn index_test() {
+        assert_eq!(index("R0", 'R'), Some(0));
+        assert_eq!(index("A12", 'A'), Some(12));
+        for name in ["R+0", "R00", "A+1", "S01", "R", "R-1", "R１", "S 1"] {
+            assert_eq!(index(name, name.chars().next().unwrap()), None, "{}", name);
+        }
+        assert_eq!(index("A0", 'R'), None);
+    }
+
+    #[test]
+    fn reference_test() {
+        // 条件が重ならないので、乱数によらず選ばれるトークは1つに決まる
+        let mut runtime = runtime(
+            r"
+            ＊OnMouseDoubleClick	（R4）＝＝Head
+            頭をつつかないで。（Rの数）
+            ＊OnMouseDoubleClick	（R4）＝＝Bust
+            （R3）の（R4）？
+            ＊OnMouseDoubleClick	（Rの数）＝＝0
+            （R3）の（R4）？
+            ",
+        );
+
+        let references = |part: &str| {
+            ["0", "0", "0", "0", part]
+                .iter()
+                .map(|r| r.to_string())
+                .collect()
+        };
+        assert_eq!(
+            runtime
+                .talk_with("OnMouseDoubleClick", references("Head"))
+                .unwrap(),
+            "頭をつつかないで。5\\e"
+        );
+        assert_eq!(
+            runtime
+                .talk_with("OnMouseDoubleClick", references("Bust"))
+                .unwrap(),
+            "0のBust？\\e"
+        );
+        // リクエストが終われば参照できない
+        assert_eq!(runtime.talk("OnMouseDoubleClick").unwrap(), "の？\\e");
+    }
+
+    #[test]
+    fn not_found_test() {
+        let mut runtime = runtime("＊OnBoot\n（未定義）こんにちは\n");
+
+        assert_eq!(runtime.talk("OnBoot").unwrap(), "こんにちは\\e");
+        assert_eq!(
+            runtime.talk("OnClose"),
+            Err(Error::TalkNotFound("OnClose".to_string()))
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! SHIORIリクエストのIDを同じ名前のトークに振り分けます
+//! ID: OnBootなら＊OnBootを実行し、ReferenceはR0、R1…として渡します
+
+use super::{Error, Runtime};
+use crate::{
+    shiori::{ErrorLevel, Method, Request, Response},
+    variable::Variables,
+};
+
+/// リクエストの前後に呼ばれる処理
+pub trait Hook {
+    /// トークに振り分ける前に呼ばれます
+    fn before(&mut self, _request: &Request, _variables: &mut Variables) {}
+
+    /// レスポンスを返す前に呼ばれます
+    fn after(&mut self, _request: &Request, _response: &mut Response) {}
+}
+
+impl Runtime {
+    /// リクエストの前後に呼ぶ処理を追加します
+    /// 追加した順に呼ばれます
+    pub fn with_hook(mut self, hook: impl Hook + Send + 'static) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// リクエストに応答します
+    /// 該当するトークがない、または条件を満たすものがなければ204を返します
+    pub fn request(&mut self, request: &Request) -> Response {
+        for hook in self.hooks.iter_mut() {
+            hook.before(request, &mut self.variables);
+        }
+
+        let mut response = self.dispatch(request);
+        // 自動保存に失敗してもトークは返す
+        if let Err(e) = self.autosave() {
+            response = response.with_error(ErrorLevel::Warning, e.to_string());
+        }
+
+        for hook in self.hooks.iter_mut() {
+            hook.after(request, &mut response);
+        }
+        response
+    }
+
+    fn dispatch(&mut self, request: &Request) -> Response {
+        let Some(id) = request.id() else {
+            return Response::bad_request("IDがありません");
+        };
+        if !self.talks.contains_key(id) {
+            return Response::no_content();
+        }
+        match self.talk_with(id, request.references()) {
+            Ok(script) if request.method == Method::Get => Response::ok(script),
+            Ok(_) => Response::no_content(),
+            Err(Error::TalkNotFound(_)) => Response::no_content(),
+            Err(e) => Response::internal_server_error(e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{shiori::Status, test_util::runtime};
+
+    fn request(method: Method, id: &str, references: &[&str]) -> Request {
+        let mut request = Request::new(method).with_header("ID", id);
+        for (n, reference) in references.iter().enumerate() {
+            request = request.with_header(format!("Reference{}", n), *reference);
+        }
+        request
+    }
+
+    #[test]
+    fn dispatch_test() {
+        let mut runtime = runtime(
+            r"
+            ＊OnBoot
+            こんにちは。
+            ＊OnMouseDoubleClick	（R4）＝＝Head
+            頭をつつかないで。
+            ＊OnSecondChange
+            ＄回数＝（回数）＋１
+            ＊OnLoop
+            （OnLoop）
+            ",
+        );
+
+        let response = runtime.request(&request(Method::Get, "OnBoot", &[]));
+        assert_eq!(response.status, Status::Ok);
+        assert_eq!(response.value(), Some("こんにちは。\\e"));
+
+        let clicked = |part| {
+            request(
+                Method::Get,
+                "OnMouseDoubleClick",
+                &["0", "0", "0", "0", part],
+            )
+        };
+        assert_eq!(
+            runtime.request(&clicked("Head")).value(),
+            Some("頭をつつかないで。\\e")
+        );
+        // 条件を満たすトークがなければ何も返さない
+        assert_eq!(runtime.request(&clicked("Bust")).status, Status::NoContent);
+
+        assert_eq!(
+            runtime
+                .request(&request(Method::Get, "OnClose", &[]))
+                .status,
+            Status::NoContent
+        );
+        assert_eq!(
+            runtime.request(&Request::new(Method::Get)).status,
+            Status::BadRequest
+        );
+
+        // NOTIFYはトークを実行するがValueは返さない
+        let response = runtime.request(&request(Method::Notify, "OnSecondChange", &[]));
+        assert_eq!(response.status, Status::NoContent);
+        assert_eq!(runtime.variables().expand("回数"), "1");
+
+        let response = runtime.request(&request(Method::Get, "OnLoop", &[]));
+        assert_eq!(response.status, Status::InternalServerError);
+        assert!(response.header("ErrorDescription").is_some());
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Hook for Recorder {
+        fn before(&mut self, request: &Request, variables: &mut Variables) {
+            let id = request.id().unwrap_or_default();
+            self.log.lock().unwrap().push(format!("before {}", id));
+            variables.set("直前のイベント", id);
+        }
+
+        fn after(&mut self, request: &Request, response: &mut Response) {
+            let id = request.id().unwrap_or_default();
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("after {} {}", id, response.status.code()));
+            *response = response.clone().with_marker("記録済み");
+        }
+    }
+
+    #[test]
+    fn hook_test() {
+        let recorder = Recorder::default();
+        let log = recorder.log.clone();
+        let mut runtime = runtime("＊OnBoot\n（直前のイベント）\n").with_hook(recorder);
+
+        let response = runtime.request(&request(Method::Get, "OnBoot", &[]));
+        assert_eq!(response.value(), Some("OnBoot\\e"));
+        assert_eq!(response.header("Marker"), Some("記録済み"));
+        runtime.request(&request(Method::Notify, "OnClose", &[]));
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                "before OnBoot",
+                "after OnBoot 200",
+                "before OnClose",
+                "after OnClose 204"
+            ]
+        );
+    }
+}
@@ -0,0 +1,547 @@
+//! （関数名、引数…）で呼び出す組み込み関数
+//! 引数は必要になったものだけ展開するので、選ばれなかった分岐の展開は起きません
+
+use parser::{
+    ast::Op,
+    token::{Content, FunctionCall},
+    Number,
+};
+
+use super::{Context, Error, Runtime};
+use crate::{eval, text};
+
+impl Runtime {
+    /// 関数を呼び出します
+    /// 組み込み関数でなければ名前だけで展開します
+    pub(super) fn call_function(
+        &mut self,
+        call: &FunctionCall,
+        ctx: &mut Context,
+    ) -> Result<String, Error> {
+        let args = call.arguments.as_slice();
+        match call.name.as_str() {
+            "call" => {
+                let label = self.argument(args, 0, ctx)?;
+                let arguments = self.arguments(&args[args.len().min(1)..], ctx)?;
+                self.call_with(&label, arguments, ctx)
+            }
+            "vncall" => {
+                // 引数は変数名として扱い、その値を渡す
+                let label = self.argument(args, 0, ctx)?;
+                let arguments = self
+                    .arguments(&args[args.len().min(1)..], ctx)?
+                    .iter()
+                    .map(|name| self.variables.expand(name))
+                    .collect();
+                self.call_with(&label, arguments, ctx)
+            }
+            "loop" => self.repeat(args, ctx),
+            "nop" => {
+                self.arguments(args, ctx)?;
+                Ok(String::new())
+            }
+            "iflist" => self.iflist(args, ctx),
+            "when" => self.when(args, ctx),
+            "計算" => eval::calculate_text(&self.argument(args, 0, ctx)?),
+            "乱数" => self.random_range(args, ctx),
+            "和" => self.fold(args, ctx, |l, r| Ok(l + r)),
+            "差" => self.fold(args, ctx, |l, r| Ok(l - r)),
+            "積" => self.fold(args, ctx, |l, r| Ok(l * r)),
+            "商" => self.fold(args, ctx, |l, r| {
+                l.checked_div(r)
+                    .ok_or_else(|| Error::Evaluation("0で割ることはできません".to_string()))
+            }),
+            "len" => Ok(self.argument(args, 0, ctx)?.chars().count().to_string()),
+            "substr" => {
+                let s = self.argument(args, 0, ctx)?;
+                let start = self.index(args, 1, ctx)?;
+                let len = match args.len() > 2 {
+                    true => Some(self.index(args, 2, ctx)?),
+                    false => None,
+                };
+                Ok(text::substr(&s, start, len))
+            }
+            "find" => {
+                let s = self.argument(args, 0, ctx)?;
+                let pattern = self.argument(args, 1, ctx)?;
+                Ok(text::find(&s, &pattern).map_or("-1".to_string(), |i| i.to_string()))
+            }
+            "replace" => {
+                let s = self.argument(args, 0, ctx)?;
+                let from = self.argument(args, 1, ctx)?;
+                let to = self.argument(args, 2, ctx)?;
+                Ok(match from.is_empty() {
+                    true => s,
+                    false => s.replace(&from, &to),
+                })
+            }
+            "zen2han" => Ok(text::zen2han(&self.argument(args, 0, ctx)?)),
+            "han2zen" => Ok(text::han2zen(&self.argument(args, 0, ctx)?)),
+            "hira2kata" => Ok(text::hira2kata(&self.argument(args, 0, ctx)?)),
+            "kata2hira" => Ok(text::kata2hira(&self.argument(args, 0, ctx)?)),
+            "reg_match" => self.reg_match(args, ctx),
+            "reg_replace" => {
+                let s = self.argument(args, 0, ctx)?;
+                let pattern = self.argument(args, 1, ctx)?;
+                let replacement = self.argument(args, 2, ctx)?;
+                let regex = self.regexes.get(&pattern)?;
+                Ok(regex.replace_all(&s, replacement.as_str()).into_owned())
+            }
+            "reg_split" => self.reg_split(args, ctx),
+            "単語の追加" => {
+                let group = self.argument(args, 0, ctx)?;
+                let word = self.argument(args, 1, ctx)?;
+                self.vocabulary.add(&group, &word);
+                self.words.reset(&group);
+                Ok(String::new())
+            }
+            "単語の削除" => {
+                let group = self.argument(args, 0, ctx)?;
+                let word = self.argument(args, 1, ctx)?;
+                self.vocabulary.remove(&group, &word);
+                self.words.reset(&group);
+                Ok(String::new())
+            }
+            "単語群の削除" => {
+                let group = self.argument(args, 0, ctx)?;
+                self.vocabulary.clear(&group);
+                self.words.reset(&group);
+                Ok(String::new())
+            }
+            "単語の数" => {
+                let group = self.argument(args, 0, ctx)?;
+                Ok(self.vocabulary.count(&group).to_string())
+            }
+            _ => self.call(&call.name, ctx),
+        }
+    }
+
+    /// n番目の引数を展開します
+    /// 引数がなければ空文字列になります
+    fn argument(
+        &mut self,
+        args: &[Vec<Content>],
+        n: usize,
+        ctx: &mut Context,
+    ) -> Result<String, Error> {
+        match args.get(n) {
+            Some(arg) => self.render_line(arg, ctx),
+            None => Ok(String::new()),
+        }
+    }
+
+    /// 引数をすべて展開します
+    fn arguments(
+        &mut self,
+        args: &[Vec<Content>],
+        ctx: &mut Context,
+    ) -> Result<Vec<String>, Error> {
+        args.iter().map(|arg| self.render_line(arg, ctx)).collect()
+    }
+
+    /// 引数A0、A1…を渡して呼び出します
+    /// 呼び出しから戻ったら呼び出し元の引数に戻します
+    fn call_with(
+        &mut self,
+        label: &str,
+        arguments: Vec<String>,
+        ctx: &mut Context,
+    ) -> Result<String, Error> {
+        let saved = std::mem::replace(&mut ctx.arguments, arguments);
+        let result = self.call(label, ctx);
+        ctx.arguments = saved;
+        result
+    }
+
+    /// （loop、ラベル、回数）または（loop、ラベル、開始、終了、増分）
+    /// 繰り返し中は（ラベルカウンタ）でカウンタを参照できます
+    fn repeat(&mut self, args: &[Vec<Content>], ctx: &mut Context) -> Result<String, Error> {
+        let label = self.argument(args, 0, ctx)?;
+        let (start, end, step) = match args.len() {
+            0..=2 => (1, self.integer(args, 1, ctx)?, 1),
+            3 => (self.integer(args, 1, ctx)?, self.integer(args, 2, ctx)?, 1),
+            _ => (
+                self.integer(args, 1, ctx)?,
+                self.integer(args, 2, ctx)?,
+                self.integer(args, 3, ctx)?,
+            ),
+        };
+        if step == 0 {
+            Err(Error::Evaluation("loopの増分が0です".to_string()))?
+        }
+
+        let counter = format!("{}カウンタ", label);
+        let saved = self.variables.remove(&counter);
+        let mut script = String::new();
+        let mut result = Ok(());
+        let mut i = Some(start);
+        while let Some(n) = i.filter(|&n| (step > 0 && n <= end) || (step < 0 && n >= end)) {
+            self.variables.set(counter.as_str(), n.to_string());
+            result = self
+                .count(ctx)
+                .and_then(|_| self.call(&label, ctx))
+                .map(|text| script.push_str(&text));
+            if result.is_err() {
+                break;
+            }
+            i = n.checked_add(step);
+        }
+        // ループの外で使っていたカウンタに戻す
+        match saved {
+            Some(value) => self.variables.set(counter, value),
+            None => self.variables.remove(&counter),
+        };
+        result.map(|_| script)
+    }
+
+    /// n番目の引数を整数として展開します
+    fn integer(
+        &mut self,
+        args: &[Vec<Content>],
+        n: usize,
+        ctx: &mut Context,
+    ) -> Result<i64, Error> {
+        Ok(self.number(args, n, ctx)?.as_f64() as i64)
+    }
+
+    /// （iflist、値、条件1、結果1、条件2、結果2…、それ以外）
+    /// 最初に値が条件に当てはまった結果を返します
+    fn iflist(&mut self, args: &[Vec<Content>], ctx: &mut Context) -> Result<String, Error> {
+        let value = self.argument(args, 0, ctx)?;
+        let mut n = 1;
+        while n + 1 < args.len() {
+            let condition = self.argument(args, n, ctx)?;
+            if eval::matches(&value, &condition) {
+                return self.argument(args, n + 1, ctx);
+            }
+            n += 2;
+        }
+        // 条件と対にならない最後の引数は、どれにも当てはまらなかったときの値
+        self.argument(args, n, ctx)
+    }
+
+    /// n番目の引数を数値として展開します
+    /// 数値でなければ0になります
+    fn number(
+        &mut self,
+        args: &[Vec<Content>],
+        n: usize,
+        ctx: &mut Context,
+    ) -> Result<Number, Error> {
+        Ok(Number::coerce(&self.argument(args, n, ctx)?))
+    }
+
+    /// n番目の引数を文字位置・文字数として展開します
+    /// 負の値は0になります
+    fn index(
+        &mut self,
+        args: &[Vec<Content>],
+        n: usize,
+        ctx: &mut Context,
+    ) -> Result<usize, Error> {
+        Ok(self.number(args, n, ctx)?.as_f64().max(0.0) as usize)
+    }
+
+    /// （reg_match、文字列、パターン）
+    /// 一致すれば1を返し、一致全体をS0、グループをS1以降に入れます
+    /// S0…はトークの中でだけ使え、変数としては保存しません
+    fn reg_match(&mut self, args: &[Vec<Content>], ctx: &mut Context) -> Result<String, Error> {
+        let s = self.argument(args, 0, ctx)?;
+        let pattern = self.argument(args, 1, ctx)?;
+        let captures = self.regexes.get(&pattern)?.captures(&s).map(|captures| {
+            captures
+                .iter()
+                .map(|m| m.map_or("", |m| m.as_str()).to_string())
+                .collect::<Vec<_>>()
+        });
+        let matched = captures.is_some();
+        ctx.captures = captures.unwrap_or_default();
+        Ok(if matched { "1" } else { "0" }.to_string())
+    }
+
+    /// （reg_split、文字列、パターン）
+    /// 分割した文字列をS0以降に入れ、個数を返します
+    fn reg_split(&mut self, args: &[Vec<Content>], ctx: &mut Context) -> Result<String, Error> {
+        let s = self.argument(args, 0, ctx)?;
+        let pattern = self.argument(args, 1, ctx)?;
+        let pieces = self
+            .regexes
+            .get(&pattern)?
+            .split(&s)
+            .map(|piece| piece.to_string())
+            .collect::<Vec<_>>();
+        let count = pieces.len();
+        ctx.captures = pieces;
+        Ok(count.to_string())
+    }
+
+    /// （乱数、最小値、最大値）
+    /// 最小値以上最大値以下の整数を返します
+    fn random_range(&mut self, args: &[Vec<Content>], ctx: &mut Context) -> Result<String, Error> {
+        let a = self.number(args, 0, ctx)?.as_f64() as i64;
+        let b = self.number(args, 1, ctx)?.as_f64() as i64;
+        let (min, max) = (a.min(b) as i128, a.max(b) as i128);
+        let offset = self.random.next_u64() as i128 % (max - min + 1);
+        Ok((min + offset).to_string())
+    }
+
+    /// （和、1、2、3）のように引数を左から順に計算します
+    fn fold(
+        &mut self,
+        args: &[Vec<Content>],
+        ctx: &mut Context,
+        op: fn(Number, Number) -> Result<Number, Error>,
+    ) -> Result<String, Error> {
+        let mut result = self.number(args, 0, ctx)?;
+        for n in 1..args.len() {
+            result = op(result, self.number(args, n, ctx)?)?;
+        }
+        Ok(result.to_string())
+    }
+
+    /// （when、条件、真のときの値、偽のときの値）
+    /// 比較演算子は展開する前の条件から探すので、展開した値に含まれる記号は演算子になりません
+    fn when(&mut self, args: &[Vec<Content>], ctx: &mut Context) -> Result<String, Error> {
+        let condition = args.first().map(Vec::as_slice).unwrap_or_default();
+        let holds = match split_comparison(condition) {
+            Some((lhs, op, rhs)) => {
+                let lhs = self.render_line(&lhs, ctx)?;
+                let rhs = self.render_line(&rhs, ctx)?;
+                eval::compare_sides(&lhs, &op, &rhs)?
+            }
+            None => eval::truth(&self.render_line(condition, ctx)?)?,
+        };
+        if holds {
+            self.argument(args, 1, ctx)
+        } else {
+            self.argument(args, 2, ctx)
+        }
+    }
+}
+
+/// 展開前の条件を、最も左にある比較演算子で左辺と右辺に分けます
+fn split_comparison(line: &[Content]) -> Option<(Vec<Content>, Op, Vec<Content>)> {
+    line.iter().enumerate().find_map(|(n, content)| {
+        let Content::Sentense(s) = content else {
+            return None;
+        };
+        let (range, op) = eval::find_comparator(s)?;
+        let mut lhs = line[..n].to_vec();
+        let mut rhs = vec![Content::Sentense(s[range.end..].to_string())];
+        lhs.push(Content::Sentense(s[..range.start].to_string()));
+        rhs.extend_from_slice(&line[n + 1..]);
+        Some((lhs, op, rhs))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::FakeClock, test_util::runtime};
+
+    #[test]
+    fn iflist_test() {
+        let src = r"
+            ＊OnBoot
+            （iflist、（現在時）、
+            ＜６、こんばんは。、
+            ＜１１、おはようございます。、
+            ＜１８、こんにちは。、
+            ＜２４、こんばんは。
+            ）
+            ＊OnClose
+            （iflist、（名前）、さくら、またね。、さようなら。）
+            ";
+        let at = |hour| {
+            runtime(src)
+                .with_clock(FakeClock::at(2024, 4, 1, hour, 0, 0).unwrap())
+                .talk("OnBoot")
+                .unwrap()
+        };
+        assert_eq!(at(5), "こんばんは。\\e");
+        assert_eq!(at(7), "おはようございます。\\e");
+        assert_eq!(at(12), "こんにちは。\\e");
+        assert_eq!(at(23), "こんばんは。\\e");
+
+        let mut runtime = runtime(src);
+        assert_eq!(runtime.talk("OnClose").unwrap(), "さようなら。\\e");
+        runtime.variables_mut().set("名前", "さくら");
+        assert_eq!(runtime.talk("OnClose").unwrap(), "またね。\\e");
+    }
+
+    #[test]
+    fn arithmetic_test() {
+        let mut runtime = runtime(
+            "＊OnBoot\n（計算、１＋２×（回数））、（和、1、２、0.5）、（差、10、３、2）、（積、1.5、２）、（商、7、2）\n＊OnClose\n（商、1、0）\n",
+        );
+        runtime.variables_mut().set("回数", "３");
+
+        assert_eq!(runtime.talk("OnBoot").unwrap(), "7、3.5、5、3、3.5\\e");
+        assert!(matches!(runtime.talk("OnClose"), Err(Error::Evaluation(_))));
+    }
+
+    #[test]
+    fn random_test() {
+        let mut runtime = runtime("＊OnBoot\n（乱数、１、３）\n＊OnClose\n（乱数、5、5）\n");
+
+        let values = (0..30)
+            .map(|_| runtime.talk("OnBoot").unwrap())
+            .collect::<Vec<_>>();
+        for value in ["1\\e", "2\\e", "3\\e"] {
+            assert!(values.contains(&value.to_string()));
+        }
+        assert!(values
+            .iter()
+            .all(|v| ["1\\e", "2\\e", "3\\e"].contains(&v.as_str())));
+        assert_eq!(runtime.talk("OnClose").unwrap(), "5\\e");
+    }
+
+    #[test]
+    fn string_test() {
+        let mut runtime = runtime(
+            "＊OnBoot\n（len、さくら）／（substr、さくらとうにゅう、３、１）／（substr、さくら、1）／（find、さくら、ら）／（find、さくら、う）／（replace、さくらさん、さん、ちゃん）\n＊OnClose\n（zen2han、ＡＢＣ　ガッコウ）／（han2zen、123）／（hira2kata、さくら）／（kata2hira、ウニュウ）\n",
+        );
+
+        assert_eq!(
+            runtime.talk("OnBoot").unwrap(),
+            "3／と／くら／2／-1／さくらちゃん\\e"
+        );
+        assert_eq!(
+            runtime.talk("OnClose").unwrap(),
+            "ABC ｶﾞｯｺｳ／１２３／サクラ／うにゅう\\e"
+        );
+    }
+
+    #[test]
+    fn regex_test() {
+        let mut runtime = runtime(
+            r"
+            ＊OnBoot
+            （reg_match、名前はさくらです、名前は(.+)です）（S1）／（reg_match、こんにちは、[0-9]+）（S1）
+            ＊OnClose
+            （reg_replace、2024年4月1日、[0-9]+、#）／（reg_split、あ,い,,う、,+）（S0）（S2）
+            ＊OnSecondChange
+            （reg_match、さくら、(）
+            ",
+        );
+
+        assert_eq!(runtime.talk("OnBoot").unwrap(), "1さくら／0\\e");
+        assert_eq!(runtime.talk("OnClose").unwrap(), "#年#月#日／3あう\\e");
+        // 結果は前の一致を残さず、変数にもならない
+        assert!(!runtime.variables().contains("S0"));
+        // [0-9]+は2回使ったが1回だけコンパイルされる
+        assert_eq!(runtime.regexes.len(), 3);
+        assert!(matches!(
+            runtime.talk("OnSecondChange"),
+            Err(Error::Evaluation(_))
+        ));
+    }
+
+    #[test]
+    fn call_test() {
+        let mut runtime = runtime(
+            r"
+            ＊OnBoot
+            （call、挨拶、さくら、朝）（A0）
+            ＊挨拶
+            （A0）、（A1）だね。（call、呼び捨て、うにゅう）（A0）
+            ＊呼び捨て
+            ／（A0）／
+            ＊OnClose
+            （vncall、挨拶、名前、時間帯）
+            ＊OnSecondChange
+            （nop、（記録））何も出ない？
+            ＊記録
+            ＄記録＝した
+            記録しました
+            ",
+        );
+
+        assert_eq!(
+            runtime.talk("OnBoot").unwrap(),
+            "さくら、朝だね。／うにゅう／さくら\\e"
+        );
+        runtime.variables_mut().set("名前", "まゆら");
+        runtime.variables_mut().set("時間帯", "夜");
+        assert_eq!(
+            runtime.talk("OnClose").unwrap(),
+            "まゆら、夜だね。／うにゅう／まゆら\\e"
+        );
+        assert_eq!(runtime.talk("OnSecondChange").unwrap(), "何も出ない？\\e");
+        assert_eq!(runtime.variables().expand("記録"), "した");
+    }
+
+    #[test]
+    fn loop_test() {
+        let mut runtime = runtime(
+            r"
+            ＊OnBoot
+            （loop、数える、3）／（loop、数える、10、4、－3）／（数えるカウンタ）
+            ＊数える
+            （数えるカウンタ）、
+            ＊OnClose
+            （loop、数える、1、2、0）
+            ",
+        );
+        runtime.variables_mut().set("数えるカウンタ", "元の値");
+
+        assert_eq!(
+            runtime.talk("OnBoot").unwrap(),
+            "1、2、3、／10、7、4、／元の値\\e"
+        );
+        assert!(matches!(runtime.talk("OnClose"), Err(Error::Evaluation(_))));
+    }
+
+    #[test]
+    fn vocabulary_test() {
+        let mut runtime = runtime(
+            r"
+            ＊OnBoot
+            （単語の追加、果物、ぶどう）（単語の削除、果物、りんご）（単語の数、果物）（果物）
+            ＊OnClose
+            （単語群の削除、果物）（単語の数、果物）（果物）
+            ＊OnSecondChange
+            （単語の追加、野菜、なす）（野菜）
+            ＠果物
+            りんご
+            ",
+        );
+
+        assert_eq!(runtime.talk("OnBoot").unwrap(), "1ぶどう\\e");
+        assert_eq!(runtime.talk("OnClose").unwrap(), "0\\e");
+        assert_eq!(runtime.talk("OnSecondChange").unwrap(), "なす\\e");
+        assert_eq!(runtime.vocabulary().count("野菜"), 1);
+    }
+
+    #[test]
+    fn when_test() {
+        let mut runtime = runtime(
+            r"
+            ＊OnBoot
+            （when、（回数）＞１、（二回目）、（初回））
+            ＊二回目
+            ＄通過＝二回目
+            また会ったね。
+            ＊初回
+            ＄通過＝初回
+            はじめまして。
+            ",
+        );
+
+        assert_eq!(runtime.talk("OnBoot").unwrap(), "はじめまして。\\e");
+        assert_eq!(runtime.variables().expand("通過"), "初回");
+
+        runtime.variables_mut().set("回数", "2");
+        runtime.variables_mut().remove("通過");
+        assert_eq!(runtime.talk("OnBoot").unwrap(), "また会ったね。\\e");
+        assert_eq!(runtime.variables().expand("通過"), "二回目");
+    }
+
+    #[test]
+    fn when_operator_test() {
+        // 展開した値の中の記号は演算子にならない
+        let mut runtime = runtime("＊OnBoot\n（when、（記号）＝＝a＜b、一致、不一致）\n");
+        runtime.variables_mut().set("記号", "a＜b");
+        assert_eq!(runtime.talk("OnBoot").unwrap(), "一致\\e");
+    }
+}
@@ -0,0 +1,246 @@
+//! 変数と単語群への変更をsavedata.txtに保存・復元します
+//! 変数の形式は里々と同じく1行に「＄変数名<TAB>値」です
+//! 値は里々と同じくそのまま書くので、さくらスクリプトの `\n` なども意味を変えずに読み書きできます
+//! 1行に収まらない改行（名前ではタブも）は保存時に取り除きます
+
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use parser::dictionary;
+
+use crate::{
+    variable::Variables,
+    vocabulary::{self, Learned},
+};
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(PathBuf, io::Error),
+    Encoding(PathBuf),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            SaveError::Encoding(path) => {
+                write!(f, "{}: 文字コードを判別できません", path.display())
+            }
+        }
+    }
+}
+
+/// savedata.txtの場所と自動保存の設定
+#[derive(Debug, Clone)]
+pub struct SaveData {
+    path: PathBuf,
+    interval: Option<Duration>, // 自動保存の間隔（Noneなら終了時のみ）
+    saved_at: Instant,          // 最後に保存（または読み込み）した時刻
+}
+
+impl SaveData {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            interval: None,
+            saved_at: Instant::now(),
+        }
+    }
+
+    /// 一定間隔で自動保存します
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 変数と単語群への変更を読み込みます
+    /// ファイルがなければどちらも空で返します
+    pub fn load(&mut self) -> Result<(Variables, Learned), SaveError> {
+        self.saved_at = Instant::now();
+        let bytes = match fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok((Variables::new(), Learned::new()))
+            }
+            Err(e) => Err(SaveError::Io(self.path.clone(), e))?,
+        };
+        let src =
+            dictionary::decode(&bytes).ok_or_else(|| SaveError::Encoding(self.path.clone()))?;
+        Ok((parse(&src), vocabulary::parse(&src)))
+    }
+
+    /// 変数と単語群への変更を保存します
+    /// 一時ファイルに書いてから置き換えるので、途中で落ちても元のファイルは壊れません
+    pub fn save(&mut self, variables: &Variables, learned: &Learned) -> Result<(), SaveError> {
+        let tmp = self.path.with_extension("tmp");
+        let text = format(variables) + &vocabulary::format(learned);
+        write_file(&tmp, &text)
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .map_err(|e| SaveError::Io(self.path.clone(), e))?;
+        self.saved_at = Instant::now();
+        Ok(())
+    }
+
+    /// 自動保存の間隔が過ぎていれば保存します
+    /// 保存した場合はtrueを返します
+    pub fn save_if_due(
+        &mut self,
+        variables: &Variables,
+        learned: &Learned,
+    ) -> Result<bool, SaveError> {
+        match self.interval {
+            Some(interval) if self.saved_at.elapsed() >= interval => {
+                self.save(variables, learned)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+fn write_file(path: &Path, text: &str) -> io::Result<()> {
+    let mut file = fs::File::create(path)?;
+    file.write_all(text.as_bytes())?;
+    file.sync_all()
+}
+
+/// savedata.txtの内容を変数にします
+/// ＄で始まらない行（コメントなど）は読み飛ばします
+pub fn parse(src: &str) -> Variables {
+    let mut variables = Variables::new();
+    for line in src.lines() {
+        let Some(line) = line.strip_prefix('＄') else {
+            continue;
+        };
+        let (name, value) = line.split_once('\t').unwrap_or((line, ""));
+        if !name.is_empty() {
+            variables.set(name, value);
+        }
+    }
+    variables
+}
+
+/// 変数をsavedata.txtの形式にします
+pub fn format(variables: &Variables) -> String {
+    variables
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                "＄{}\t{}\r\n",
+                one_line_name(name),
+                one_line(&value.to_string())
+            )
+        })
+        .collect()
+}
+
+/// 1行に収まるよう値から改行を取り除きます
+pub(crate) fn one_line(value: &str) -> String {
+    value.replace(['\r', '\n'], "")
+}
+
+/// 名前から改行と区切りのタブを取り除きます
+pub(crate) fn one_line_name(name: &str) -> String {
+    name.replace(['\r', '\n', '\t'], "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn parse_test() {
+        let variables = parse("＃コメント\r\n＄回数\t１０\r\n＄名前\tさくら\tさん\r\n＄空\r\n\r\n");
+
+        assert_eq!(variables.expand("回数"), "１０");
+        assert_eq!(variables.expand("名前"), "さくら\tさん");
+        assert!(variables.contains("空"));
+        assert_eq!(variables.len(), 3);
+        assert_eq!(parse(&format(&variables)), variables);
+    }
+
+    #[test]
+    fn one_line_test() {
+        let mut variables = Variables::new();
+        variables.set("トーク", "x\r\n＄b\tinjected");
+        variables.set("名\t前", "さくら");
+
+        // 改行は取り除くので、値から別の行を作れない
+        let text = format(&variables);
+        assert_eq!(text.lines().count(), 2);
+        assert!(text.contains("＄トーク\tx＄b\tinjected\r\n"));
+        assert!(text.contains("＄名前\tさくら\r\n"));
+        assert_eq!(parse(&text).expand("トーク"), "x＄b\tinjected");
+    }
+
+    #[test]
+    fn sakura_script_test() {
+        // 里々が書いたsavedata.txtのさくらスクリプトはそのまま読み、そのまま書く
+        let src = "＄挨拶\tやあ\\nまたね\\w8\\\\\r\n";
+        let variables = parse(src);
+        assert_eq!(variables.expand("挨拶"), "やあ\\nまたね\\w8\\\\");
+        assert_eq!(format(&variables), src);
+    }
+
+    #[test]
+    fn save_test() {
+        let dir = temp_dir("savedata");
+        let path = dir.join("savedata.txt");
+
+        let mut savedata = SaveData::new(&path);
+        assert!(savedata.load().unwrap().0.is_empty());
+
+        let mut variables = Variables::new();
+        variables.set("回数", "1");
+        savedata.save(&variables, &Learned::new()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "＄回数\t1\r\n");
+        assert!(!dir.join("savedata.tmp").exists());
+        assert_eq!(SaveData::new(&path).load().unwrap().0, variables);
+
+        // 単語群への変更も同じファイルに保存する
+        let learned = vocabulary::parse("＠果物\tぶどう\r\n");
+        savedata.save(&variables, &learned).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "＄回数\t1\r\n＠果物\tぶどう\r\n"
+        );
+        assert_eq!(savedata.load().unwrap(), (variables, learned));
+
+        // Shift_JISで保存された里々のファイルも読める
+        // ＄名前<TAB>さくら
+        let bytes = [
+            0x81, 0x90, 0x96, 0xBC, 0x91, 0x4F, 0x09, 0x82, 0xB3, 0x82, 0xAD, 0x82, 0xE7, 0x0D,
+            0x0A,
+        ];
+        fs::write(&path, bytes).unwrap();
+        assert_eq!(savedata.load().unwrap().0.expand("名前"), "さくら");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn interval_test() {
+        let dir = temp_dir("savedata-interval");
+        let path = dir.join("savedata.txt");
+        let variables = Variables::new();
+        let learned = Learned::new();
+
+        assert!(!SaveData::new(&path)
+            .save_if_due(&variables, &learned)
+            .unwrap());
+        let mut savedata = SaveData::new(&path).with_interval(Duration::ZERO);
+        assert!(savedata.save_if_due(&variables, &learned).unwrap());
+        assert!(path.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,216 @@
+//! 同じ名前のトーク・無名のランダムトークから1つを選びます
+
+use std::collections::HashMap;
+
+use crate::random::Rng;
+
+/// 重複を避ける方針
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    Random,        // 毎回完全にランダム
+    Recent(usize), // 直近n回に選んだものを避ける
+    Shuffle,       // 候補をすべて選び切るまで同じものを選ばない（里々の既定）
+}
+
+/// トークの選択履歴
+/// Runtimeが保持するのでリクエストをまたいで引き継がれます
+#[derive(Debug, Clone)]
+pub struct TalkSelector {
+    policy: Policy,
+    history: HashMap<Option<String>, Vec<usize>>, // トーク名（無名はNone） → 選んだ添字（古い順）
+}
+
+impl TalkSelector {
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            history: HashMap::new(),
+        }
+    }
+
+    pub fn policy(&self) -> Policy {
+        self.policy
+    }
+
+    /// 選択履歴を返します
+    pub fn history(&self, label: Option<&str>) -> &[usize] {
+        self.history
+            .get(&label.map(|l| l.to_string()))
+            .map_or(&[], |h| h.as_slice())
+    }
+
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    /// 候補から1つ選びます
+    /// indicesは条件で絞り込む前のすべての添字、candidatesはそのうち条件を満たすものです
+    /// 候補が空の場合はNoneを返します
+    pub fn choose(
+        &mut self,
+        label: Option<&str>,
+        indices: &[usize],
+        candidates: &[usize],
+        random: &mut dyn Rng,
+    ) -> Option<usize> {
+        if candidates.is_empty() {
+            return None;
+        }
+        let history = self
+            .history
+            .entry(label.map(|l| l.to_string()))
+            .or_default();
+
+        let chosen = match self.policy {
+            Policy::Random => candidates[random.below(candidates.len())],
+            Policy::Recent(n) => {
+                let recent = &history[history.len().saturating_sub(n)..];
+                pick_unused(candidates, recent, random)
+            }
+            Policy::Shuffle => {
+                // 全候補を選び切ったら履歴を空にして次の一巡に入る
+                // ただし直前のものは続けて選ばないようにする
+                if candidates.iter().all(|c| history.contains(c)) {
+                    let last = history.last().copied();
+                    history.clear();
+                    pick_unused(candidates, last.as_slice(), random)
+                } else {
+                    pick_unused(candidates, history, random)
+                }
+            }
+        };
+
+        history.push(chosen);
+        // 履歴が際限なく伸びないよう、古いものから捨てる
+        // 候補が条件で一時的に減っても一巡の途中を忘れないよう、絞り込む前の数を基準にする
+        let limit = match self.policy {
+            Policy::Recent(n) => n.max(1),
+            _ => indices.len() * 2,
+        };
+        if history.len() > limit {
+            history.drain(..history.len() - limit);
+        }
+        Some(chosen)
+    }
+}
+
+impl Default for TalkSelector {
+    fn default() -> Self {
+        Self::new(Policy::Shuffle)
+    }
+}
+
+/// 履歴にない候補から選びます
+/// すべて履歴にある場合は候補全体から選びます
+fn pick_unused(candidates: &[usize], used: &[usize], random: &mut dyn Rng) -> usize {
+    let unused = candidates
+        .iter()
+        .filter(|c| !used.contains(c))
+        .copied()
+        .collect::<Vec<_>>();
+    if unused.is_empty() {
+        candidates[random.below(candidates.len())]
+    } else {
+        unused[random.below(unused.len())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Random;
+
+    #[test]
+    fn shuffle_test() {
+        let mut selector = TalkSelector::new(Policy::Shuffle);
+        let mut random = Random::new(0);
+        let candidates = [3, 5, 8];
+
+        for _ in 0..10 {
+            let mut round = (0..3)
+                .map(|_| {
+                    selector
+                        .choose(None, &candidates, &candidates, &mut random)
+                        .unwrap()
+                })
+                .collect::<Vec<_>>();
+            round.sort();
+            assert_eq!(round, vec![3, 5, 8]);
+        }
+    }
+
+    #[test]
+    fn no_immediate_repeat_test() {
+        let mut selector = TalkSelector::new(Policy::Shuffle);
+        let mut random = Random::new(1);
+        let candidates = [0, 1];
+
+        let picks = (0..20)
+            .map(|_| {
+                selector
+                    .choose(Some("挨拶"), &candidates, &candidates, &mut random)
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert!(picks.windows(2).all(|w| w[0] != w[1]));
+        assert!(selector.history(Some("挨拶")).len() <= 2);
+        assert!(selector.history(None).is_empty());
+    }
+
+    #[test]
+    fn filtered_shuffle_test() {
+        let mut selector = TalkSelector::new(Policy::Shuffle);
+        let mut random = Random::new(3);
+        let indices = [0, 1, 2, 3];
+
+        let mut chosen = (0..2)
+            .map(|_| {
+                selector
+                    .choose(None, &indices, &indices, &mut random)
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        // 条件で候補が1つに減っても、それまでの一巡の履歴は残る
+        let rest = indices
+            .iter()
+            .filter(|i| !chosen.contains(i))
+            .copied()
+            .collect::<Vec<_>>();
+        chosen.push(
+            selector
+                .choose(None, &indices, &rest[..1], &mut random)
+                .unwrap(),
+        );
+        assert_eq!(selector.history(None), chosen);
+
+        assert_eq!(
+            selector.choose(None, &indices, &indices, &mut random),
+            Some(rest[1])
+        );
+    }
+
+    #[test]
+    fn recent_test() {
+        let mut selector = TalkSelector::new(Policy::Recent(2));
+        let mut random = Random::new(2);
+        let candidates = [0, 1, 2, 3];
+
+        let picks = (0..30)
+            .map(|_| {
+                selector
+                    .choose(None, &candidates, &candidates, &mut random)
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert!(picks
+            .windows(3)
+            .all(|w| w[0] != w[1] && w[1] != w[2] && w[0] != w[2]));
+    }
+
+    #[test]
+    fn empty_test() {
+        let mut selector = TalkSelector::default();
+
+        assert_eq!(selector.choose(None, &[], &[], &mut Random::new(0)), None);
+    }
+}
@@ -0,0 +1,483 @@
+//! SHIORI/3.0のリクエストの解析とレスポンスの組み立て
+//! 1行目が開始行、続いて「名前: 値」のヘッダがCRLF区切りで並び、空行で終わります
+
+use std::fmt;
+
+pub const VERSION: &str = "SHIORI/3.0";
+
+/// 受け付けるReferenceの番号の上限
+pub const MAX_REFERENCE: usize = 255;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    Empty,             // 空のリクエスト
+    StartLine(String), // 開始行の形式が不正
+    Method(String),    // 知らないメソッド
+    Version(String),   // SHIORI/3.0以外
+    Header(String),    // 「名前: 値」になっていないヘッダ
+    Reference(String), // 番号が上限を超えたReference
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "リクエストが空です"),
+            ParseError::StartLine(line) => write!(f, "開始行が不正です: {}", line),
+            ParseError::Method(method) => write!(f, "メソッドが不正です: {}", method),
+            ParseError::Version(version) => write!(f, "未対応のバージョンです: {}", version),
+            ParseError::Header(line) => write!(f, "ヘッダが不正です: {}", line),
+            ParseError::Reference(name) => write!(f, "Referenceの番号が大きすぎます: {}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    Get,    // 返答（Value）を求める
+    Notify, // 通知のみ（Valueは使われない）
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Method::Get => f.write_str("GET"),
+            Method::Notify => f.write_str("NOTIFY"),
+        }
+    }
+}
+
+/// SHIORI/3.0のリクエスト
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: Method,
+    pub headers: Vec<(String, String)>, // 受け取った順のヘッダ
+}
+
+impl Request {
+    pub fn new(method: Method) -> Self {
+        Self {
+            method,
+            headers: vec![],
+        }
+    }
+
+    /// ヘッダを追加します
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// リクエストを解析します
+    /// 改行はCRLFのほかLFだけでも受け付けます
+    /// ヘッダの値の前後の空白は取り除きます
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut lines = text.lines();
+        let start = lines
+            .next()
+            .filter(|line| !line.is_empty())
+            .ok_or(ParseError::Empty)?;
+        let (method, version) = start
+            .split_once(' ')
+            .ok_or_else(|| ParseError::StartLine(start.to_string()))?;
+        let method = match method {
+            "GET" => Method::Get,
+            "NOTIFY" => Method::Notify,
+            _ => Err(ParseError::Method(method.to_string()))?,
+        };
+        if version != VERSION {
+            Err(ParseError::Version(version.to_string()))?
+        }
+
+        let mut request = Request::new(method);
+        for line in lines.take_while(|line| !line.is_empty()) {
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| ParseError::Header(line.to_string()))?;
+            if reference_index(name).is_some_and(|n| n > MAX_REFERENCE) {
+                Err(ParseError::Reference(name.to_string()))?
+            }
+            request
+                .headers
+                .push((name.to_string(), value.trim().to_string()));
+        }
+        Ok(request)
+    }
+
+    /// 名前が一致する最初のヘッダの値を返します
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// イベント名（ID）
+    pub fn id(&self) -> Option<&str> {
+        self.header("ID")
+    }
+
+    pub fn charset(&self) -> Option<&str> {
+        self.header("Charset")
+    }
+
+    /// 送り主のベースウェア名
+    pub fn sender(&self) -> Option<&str> {
+        self.header("Sender")
+    }
+
+    /// local（同じPC内）またはexternal（外部）
+    pub fn security_level(&self) -> Option<&str> {
+        self.header("SecurityLevel")
+    }
+
+    /// n番目のReference
+    pub fn reference(&self, n: usize) -> Option<&str> {
+        self.header(&format!("Reference{}", n))
+    }
+
+    /// Reference0から最後のReferenceまでを返します
+    /// 途中で抜けている番号は空文字列になります
+    /// MAX_REFERENCEを超える番号は無視します
+    pub fn references(&self) -> Vec<String> {
+        let mut references: Vec<Option<&str>> = vec![];
+        for (name, value) in &self.headers {
+            let Some(n) = reference_index(name).filter(|&n| n <= MAX_REFERENCE) else {
+                continue;
+            };
+            let Some(len) = n.checked_add(1) else {
+                continue;
+            };
+            if references.len() < len {
+                references.resize(len, None);
+            }
+            // 同じ番号が複数あれば最初のものを使う（headerと同じ）
+            references[n].get_or_insert(value);
+        }
+        references
+            .into_iter()
+            .map(|value| value.unwrap_or_default().to_string())
+            .collect()
+    }
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}\r\n", self.method, VERSION)?;
+        write_headers(f, &self.headers)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Ok,                  // 200 Valueあり
+    NoContent,           // 204 返すものがない
+    BadRequest,          // 400 リクエストが不正
+    InternalServerError, // 500 SHIORI内部のエラー
+}
+
+impl Status {
+    pub fn code(&self) -> u16 {
+        match self {
+            Status::Ok => 200,
+            Status::NoContent => 204,
+            Status::BadRequest => 400,
+            Status::InternalServerError => 500,
+        }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Status::Ok => "OK",
+            Status::NoContent => "No Content",
+            Status::BadRequest => "Bad Request",
+            Status::InternalServerError => "Internal Server Error",
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.code(), self.reason())
+    }
+}
+
+/// ErrorLevelヘッダの値
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorLevel {
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Critical,
+}
+
+impl fmt::Display for ErrorLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorLevel::Info => "info",
+            ErrorLevel::Notice => "notice",
+            ErrorLevel::Warning => "warning",
+            ErrorLevel::Error => "error",
+            ErrorLevel::Critical => "critical",
+        })
+    }
+}
+
+/// SHIORI/3.0のレスポンス
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: Status,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Response {
+    /// CharsetとSenderだけを持つレスポンスを作ります
+    pub fn new(status: Status) -> Self {
+        Self {
+            status,
+            headers: vec![
+                ("Charset".to_string(), "UTF-8".to_string()),
+                ("Sender".to_string(), env!("CARGO_PKG_NAME").to_string()),
+            ],
+        }
+    }
+
+    /// さくらスクリプトを返す200のレスポンス
+    pub fn ok(value: impl Into<String>) -> Self {
+        Self::new(Status::Ok).with_value(value)
+    }
+
+    pub fn no_content() -> Self {
+        Self::new(Status::NoContent)
+    }
+
+    pub fn bad_request(description: impl Into<String>) -> Self {
+        Self::new(Status::BadRequest).with_error(ErrorLevel::Error, description)
+    }
+
+    pub fn internal_server_error(description: impl Into<String>) -> Self {
+        Self::new(Status::InternalServerError).with_error(ErrorLevel::Error, description)
+    }
+
+    /// ヘッダを追加します
+    /// 値に含まれる改行はヘッダを壊すので取り除きます
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let value = value.into().replace(['\r', '\n'], "");
+        self.headers.push((name.into(), value));
+        self
+    }
+
+    pub fn with_value(self, value: impl Into<String>) -> Self {
+        self.with_header("Value", value)
+    }
+
+    /// バルーンのマーカーに表示する文字列
+    pub fn with_marker(self, marker: impl Into<String>) -> Self {
+        self.with_header("Marker", marker)
+    }
+
+    /// ベースウェアに伝えるエラー
+    /// 2つ目以降はErrorLevelとErrorDescriptionの値に\x01区切りで追加します
+    pub fn with_error(self, level: ErrorLevel, description: impl Into<String>) -> Self {
+        let description = description.into().replace('\x01', "");
+        self.with_joined_header("ErrorLevel", level.to_string())
+            .with_joined_header("ErrorDescription", description)
+    }
+
+    /// 同じ名前のヘッダがあれば値を\x01区切りで追加し、なければヘッダを追加します
+    fn with_joined_header(mut self, name: &str, value: String) -> Self {
+        let value = value.replace(['\r', '\n'], "");
+        match self.headers.iter_mut().find(|(n, _)| n == name) {
+            Some((_, joined)) => {
+                joined.push('\x01');
+                joined.push_str(&value);
+            }
+            None => self.headers.push((name.to_string(), value)),
+        }
+        self
+    }
+
+    /// 名前が一致する最初のヘッダの値を返します
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn value(&self) -> Option<&str> {
+        self.header("Value")
+    }
+}
+
+impl fmt::Display for Response {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}\r\n", VERSION, self.status)?;
+        write_headers(f, &self.headers)
+    }
+}
+
+/// ReferenceNのNを返します
+fn reference_index(name: &str) -> Option<usize> {
+    name.strip_prefix("Reference")?.parse().ok()
+}
+
+fn write_headers(f: &mut fmt::Formatter<'_>, headers: &[(String, String)]) -> fmt::Result {
+    for (name, value) in headers {
+        write!(f, "{}: {}\r\n", name, value)?;
+    }
+    f.write_str("\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // SSPが送ってきたリクエスト
+    const ON_BOOT: &str = "GET SHIORI/3.0\r\n\
+        Charset: UTF-8\r\n\
+        Sender: SSP\r\n\
+        SenderType: internal,raise\r\n\
+        SecurityLevel: local\r\n\
+        Status: balloon(0=0)\r\n\
+        ID: OnBoot\r\n\
+        BaseID: OnBoot\r\n\
+        Reference0: マスターシェル\r\n\
+        Reference6: \r\n\
+        Reference7: \r\n\
+        \r\n";
+
+    const ON_MOUSE_DOUBLE_CLICK: &str = "GET SHIORI/3.0\r\n\
+        Charset: UTF-8\r\n\
+        Sender: SSP\r\n\
+        SenderType: internal\r\n\
+        SecurityLevel: local\r\n\
+        ID: OnMouseDoubleClick\r\n\
+        Reference0: 138\r\n\
+        Reference1: 231\r\n\
+        Reference2: 0\r\n\
+        Reference3: 0\r\n\
+        Reference4: Head\r\n\
+        Reference5: 0\r\n\
+        Reference6: mouse\r\n\
+        \r\n";
+
+    const ON_SECOND_CHANGE: &str = "NOTIFY SHIORI/3.0\r\n\
+        Charset: UTF-8\r\n\
+        Sender: SSP\r\n\
+        SenderType: internal,notify\r\n\
+        SecurityLevel: local\r\n\
+        ID: OnSecondChange\r\n\
+        Reference0: 3\r\n\
+        Reference1: 0\r\n\
+        Reference2: 0\r\n\
+        Reference3: 1\r\n\
+        Reference4: 1\r\n\
+        \r\n";
+
+    #[test]
+    fn request_test() {
+        let request = Request::parse(ON_BOOT).unwrap();
+        assert_eq!(request.method, Method::Get);
+        assert_eq!(request.id(), Some("OnBoot"));
+        assert_eq!(request.charset(), Some("UTF-8"));
+        assert_eq!(request.sender(), Some("SSP"));
+        assert_eq!(request.security_level(), Some("local"));
+        assert_eq!(request.reference(0), Some("マスターシェル"));
+        assert_eq!(request.reference(6), Some(""));
+        assert_eq!(request.reference(8), None);
+        assert_eq!(
+            request.references(),
+            vec!["マスターシェル", "", "", "", "", "", "", ""]
+        );
+
+        let request = Request::parse(ON_SECOND_CHANGE).unwrap();
+        assert_eq!(request.method, Method::Notify);
+        assert_eq!(request.references().len(), 5);
+    }
+
+    #[test]
+    fn round_trip_test() {
+        for capture in [ON_BOOT, ON_MOUSE_DOUBLE_CLICK, ON_SECOND_CHANGE] {
+            assert_eq!(Request::parse(capture).unwrap().to_string(), capture);
+        }
+        let request = Request::parse("GET SHIORI/3.0\nID: OnBoot\n\n").unwrap();
+        assert_eq!(request.to_string(), "GET SHIORI/3.0\r\nID: OnBoot\r\n\r\n");
+
+        // 値が空のヘッダは「: 」の空白が省かれることがある
+        let request = Request::parse("GET SHIORI/3.0\r\nID:OnBoot\r\nReference6:\r\n\r\n").unwrap();
+        assert_eq!(request.id(), Some("OnBoot"));
+        assert_eq!(request.reference(6), Some(""));
+    }
+
+    #[test]
+    fn invalid_request_test() {
+        assert_eq!(Request::parse(""), Err(ParseError::Empty));
+        assert_eq!(
+            Request::parse("GET\r\n\r\n"),
+            Err(ParseError::StartLine("GET".to_string()))
+        );
+        assert_eq!(
+            Request::parse("POST SHIORI/3.0\r\n\r\n"),
+            Err(ParseError::Method("POST".to_string()))
+        );
+        assert_eq!(
+            Request::parse("GET SHIORI/2.6\r\n\r\n"),
+            Err(ParseError::Version("SHIORI/2.6".to_string()))
+        );
+        assert_eq!(
+            Request::parse("GET SHIORI/3.0\r\nID OnBoot\r\n\r\n"),
+            Err(ParseError::Header("ID OnBoot".to_string()))
+        );
+        assert_eq!(
+            Request::parse("GET SHIORI/3.0\r\nReference18446744073709551615: x\r\n\r\n"),
+            Err(ParseError::Reference(
+                "Reference18446744073709551615".to_string()
+            ))
+        );
+        assert_eq!(
+            Request::parse("GET SHIORI/3.0\r\nReference4000000000: x\r\n\r\n"),
+            Err(ParseError::Reference("Reference4000000000".to_string()))
+        );
+        assert!(Request::parse("GET SHIORI/3.0\r\nReference255: x\r\n\r\n").is_ok());
+
+        // 組み立てたリクエストでも上限を超える番号は使わない
+        let request = Request::new(Method::Get)
+            .with_header("Reference1", "a")
+            .with_header("Reference99999999999999999999", "b")
+            .with_header("Reference4000000000", "c");
+        assert_eq!(request.references(), vec!["", "a"]);
+    }
+
+    #[test]
+    fn response_test() {
+        let response = Response::ok("\\0こんにちは\\e").with_marker("1/3");
+        assert_eq!(
+            response.to_string(),
+            "SHIORI/3.0 200 OK\r\n\
+             Charset: UTF-8\r\n\
+             Sender: web-satori\r\n\
+             Value: \\0こんにちは\\e\r\n\
+             Marker: 1/3\r\n\
+             \r\n"
+        );
+        assert_eq!(response.value(), Some("\\0こんにちは\\e"));
+
+        assert_eq!(
+            Response::no_content().to_string(),
+            "SHIORI/3.0 204 No Content\r\nCharset: UTF-8\r\nSender: web-satori\r\n\r\n"
+        );
+        assert_eq!(
+            Response::internal_server_error("トークが見つかりません\r\n")
+                .with_error(ErrorLevel::Warning, "2つ目")
+                .to_string(),
+            "SHIORI/3.0 500 Internal Server Error\r\n\
+             Charset: UTF-8\r\n\
+             Sender: web-satori\r\n\
+             ErrorLevel: error\x01warning\r\n\
+             ErrorDescription: トークが見つかりません\x012つ目\r\n\
+             \r\n"
+        );
+        assert_eq!(Response::bad_request("").status.code(), 400);
+    }
+}
@@ -0,0 +1,18 @@
+//! テストで共通して使う補助関数
+
+use std::{fs, path::PathBuf};
+
+use crate::{random::Random, runtime::Runtime};
+
+/// 辞書テキストから、乱数を固定したランタイムを作ります
+pub fn runtime(src: &str) -> Runtime {
+    Runtime::new(parser::block::parse(src, 0).unwrap()).with_random(Random::new(0))
+}
+
+/// テスト用の空の一時ディレクトリを作ります
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("satori-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,197 @@
+//! 文字列関数で使う変換（全角・半角、ひらがな・カタカナ）と正規表現のキャッシュ
+//! 位置と長さはすべて文字単位です
+
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::runtime::Error;
+
+/// 全角カタカナと半角カタカナの対応（濁点・半濁点つきは2文字になる）
+#[rustfmt::skip]
+const KATAKANA: [(&str, &str); 89] = [
+    ("。", "｡"), ("「", "｢"), ("」", "｣"), ("、", "､"), ("・", "･"),
+    ("ヲ", "ｦ"), ("ァ", "ｧ"), ("ィ", "ｨ"), ("ゥ", "ｩ"), ("ェ", "ｪ"),
+    ("ォ", "ｫ"), ("ャ", "ｬ"), ("ュ", "ｭ"), ("ョ", "ｮ"), ("ッ", "ｯ"),
+    ("ー", "ｰ"), ("ア", "ｱ"), ("イ", "ｲ"), ("ウ", "ｳ"), ("エ", "ｴ"),
+    ("オ", "ｵ"), ("カ", "ｶ"), ("キ", "ｷ"), ("ク", "ｸ"), ("ケ", "ｹ"),
+    ("コ", "ｺ"), ("サ", "ｻ"), ("シ", "ｼ"), ("ス", "ｽ"), ("セ", "ｾ"),
+    ("ソ", "ｿ"), ("タ", "ﾀ"), ("チ", "ﾁ"), ("ツ", "ﾂ"), ("テ", "ﾃ"),
+    ("ト", "ﾄ"), ("ナ", "ﾅ"), ("ニ", "ﾆ"), ("ヌ", "ﾇ"), ("ネ", "ﾈ"),
+    ("ノ", "ﾉ"), ("ハ", "ﾊ"), ("ヒ", "ﾋ"), ("フ", "ﾌ"), ("ヘ", "ﾍ"),
+    ("ホ", "ﾎ"), ("マ", "ﾏ"), ("ミ", "ﾐ"), ("ム", "ﾑ"), ("メ", "ﾒ"),
+    ("モ", "ﾓ"), ("ヤ", "ﾔ"), ("ユ", "ﾕ"), ("ヨ", "ﾖ"), ("ラ", "ﾗ"),
+    ("リ", "ﾘ"), ("ル", "ﾙ"), ("レ", "ﾚ"), ("ロ", "ﾛ"), ("ワ", "ﾜ"),
+    ("ン", "ﾝ"), ("゛", "ﾞ"), ("゜", "ﾟ"), ("ヴ", "ｳﾞ"), ("ガ", "ｶﾞ"),
+    ("ギ", "ｷﾞ"), ("グ", "ｸﾞ"), ("ゲ", "ｹﾞ"), ("ゴ", "ｺﾞ"), ("ザ", "ｻﾞ"),
+    ("ジ", "ｼﾞ"), ("ズ", "ｽﾞ"), ("ゼ", "ｾﾞ"), ("ゾ", "ｿﾞ"), ("ダ", "ﾀﾞ"),
+    ("ヂ", "ﾁﾞ"), ("ヅ", "ﾂﾞ"), ("デ", "ﾃﾞ"), ("ド", "ﾄﾞ"), ("バ", "ﾊﾞ"),
+    ("ビ", "ﾋﾞ"), ("ブ", "ﾌﾞ"), ("ベ", "ﾍﾞ"), ("ボ", "ﾎﾞ"), ("パ", "ﾊﾟ"),
+    ("ピ", "ﾋﾟ"), ("プ", "ﾌﾟ"), ("ペ", "ﾍﾟ"), ("ポ", "ﾎﾟ"),
+];
+
+/// 全角の英数字・記号・空白・カタカナを半角にします
+pub fn zen2han(s: &str) -> String {
+    let mut result = String::new();
+    for c in s.chars() {
+        match c {
+            '　' => result.push(' '),
+            '！'..='～' => result.push(char::from_u32(c as u32 - 0xFEE0).unwrap_or(c)),
+            _ => match KATAKANA.iter().find(|(zen, _)| zen.starts_with(c)) {
+                Some((_, han)) => result.push_str(han),
+                None => result.push(c),
+            },
+        }
+    }
+    result
+}
+
+/// 半角の英数字・記号・空白・カタカナを全角にします
+/// 濁点・半濁点が続く半角カタカナは1文字にまとめます
+pub fn han2zen(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' => result.push('　'),
+            '!'..='~' => result.push(char::from_u32(c as u32 + 0xFEE0).unwrap_or(c)),
+            _ => {
+                let combined = chars.peek().and_then(|next| {
+                    let han = format!("{}{}", c, next);
+                    KATAKANA.iter().find(|(_, h)| *h == han)
+                });
+                if let Some((zen, _)) = combined {
+                    chars.next();
+                    result.push_str(zen);
+                } else {
+                    match KATAKANA
+                        .iter()
+                        .find(|(_, han)| han.starts_with(c) && han.chars().count() == 1)
+                    {
+                        Some((zen, _)) => result.push_str(zen),
+                        None => result.push(c),
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+/// ひらがなをカタカナにします
+pub fn hira2kata(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            'ぁ'..='ゖ' | 'ゝ' | 'ゞ' => char::from_u32(c as u32 + 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+/// カタカナをひらがなにします
+pub fn kata2hira(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            'ァ'..='ヶ' | 'ヽ' | 'ヾ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+/// start文字目からlen文字を取り出します（lenがNoneなら末尾まで）
+pub fn substr(s: &str, start: usize, len: Option<usize>) -> String {
+    let chars = s.chars().skip(start);
+    match len {
+        Some(len) => chars.take(len).collect(),
+        None => chars.collect(),
+    }
+}
+
+/// 最初に見つかった位置（文字単位）を返します
+pub fn find(s: &str, pattern: &str) -> Option<usize> {
+    s.find(pattern).map(|i| s[..i].chars().count())
+}
+
+/// 保持する正規表現の最大数
+/// 超えたらすべて捨ててから保持し直します
+pub const MAX_REGEXES: usize = 256;
+
+/// コンパイル済みの正規表現を保持します
+#[derive(Debug, Clone, Default)]
+pub struct RegexCache {
+    patterns: HashMap<String, Regex>,
+}
+
+impl RegexCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 正規表現を返します
+    /// 初めて使うパターンはコンパイルして保持します
+    pub fn get(&mut self, pattern: &str) -> Result<&Regex, Error> {
+        if !self.patterns.contains_key(pattern) {
+            let regex = Regex::new(pattern)
+                .map_err(|e| Error::Evaluation(format!("正規表現が不正です: {}", e)))?;
+            if self.patterns.len() >= MAX_REGEXES {
+                self.patterns.clear();
+            }
+            self.patterns.insert(pattern.to_string(), regex);
+        }
+        Ok(&self.patterns[pattern])
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn width_test() {
+        assert_eq!(zen2han("ＡＢＣ　１２３！"), "ABC 123!");
+        assert_eq!(han2zen("ABC 123!"), "ＡＢＣ　１２３！");
+        assert_eq!(zen2han("ガッコウ、パン"), "ｶﾞｯｺｳ､ﾊﾟﾝ");
+        assert_eq!(han2zen("ｶﾞｯｺｳ､ﾊﾟﾝ"), "ガッコウ、パン");
+        assert_eq!(han2zen("ﾞｱ"), "゛ア");
+        assert_eq!(zen2han("さくら"), "さくら");
+    }
+
+    #[test]
+    fn kana_test() {
+        assert_eq!(hira2kata("さくらとうにゅう"), "サクラトウニュウ");
+        assert_eq!(kata2hira("サクラトウニュウー"), "さくらとうにゅうー");
+        assert_eq!(hira2kata("ABC漢字"), "ABC漢字");
+    }
+
+    #[test]
+    fn substr_test() {
+        assert_eq!(substr("さくらとうにゅう", 3, Some(1)), "と");
+        assert_eq!(substr("さくらとうにゅう", 4, None), "うにゅう");
+        assert_eq!(substr("さくら", 5, Some(1)), "");
+        assert_eq!(find("さくらとうにゅう", "うにゅう"), Some(4));
+        assert_eq!(find("さくら", "うにゅう"), None);
+    }
+
+    #[test]
+    fn regex_cache_test() {
+        let mut cache = RegexCache::new();
+        assert!(cache.get("^[0-9]+$").unwrap().is_match("123"));
+        assert!(cache.get("^[0-9]+$").unwrap().is_match("456"));
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get("(").is_err());
+        assert_eq!(cache.len(), 1);
+
+        for n in 0..MAX_REGEXES * 2 {
+            cache.get(&n.to_string()).unwrap();
+        }
+        assert!(cache.len() <= MAX_REGEXES);
+    }
+}
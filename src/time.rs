@@ -0,0 +1,104 @@
+//! 日時のシステム変数（現在時、現在曜日、起動秒など）
+
+use chrono::{Datelike, Timelike};
+
+use crate::clock::Clock;
+
+/// 日時のシステム変数を展開します
+/// 該当する変数がなければNoneを返します
+/// 時計は必要な値だけ読みます（OSの起動時間の取得はファイルを読むため）
+pub fn variable(name: &str, clock: &dyn Clock, booted: i64) -> Option<String> {
+    let now = || clock.now();
+    let elapsed = || (clock.unix_time() - booted).max(0);
+    let os = || clock.uptime().as_secs() as i64;
+    let value = match name {
+        "現在年" => now().year() as i64,
+        "現在月" => now().month() as i64,
+        "現在日" => now().day() as i64,
+        "現在曜日" => now().weekday().num_days_from_sunday() as i64, // 日曜日が0
+        "現在時" => now().hour() as i64,
+        "現在分" => now().minute() as i64,
+        "現在秒" => now().second() as i64,
+        "現在UNIX時間" => clock.unix_time(),
+        // ゴーストの起動からの経過時間（時・分・秒に分けたもの）
+        "起動時" => elapsed() / 3600,
+        "起動分" => elapsed() / 60 % 60,
+        "起動秒" => elapsed() % 60,
+        "単純起動分" => elapsed() / 60,
+        "単純起動秒" => elapsed(),
+        // OSの起動からの経過時間
+        "OS起動時" => os() / 3600,
+        "OS起動分" => os() / 60 % 60,
+        "OS起動秒" => os() % 60,
+        "単純OS起動分" => os() / 60,
+        "単純OS起動秒" => os(),
+        _ => return None,
+    };
+    Some(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::clock::FakeClock;
+
+    #[test]
+    fn now_test() {
+        let clock = FakeClock::at(2024, 4, 1, 7, 5, 9).unwrap();
+        let get = |name| variable(name, &clock, 0).unwrap();
+
+        assert_eq!(get("現在年"), "2024");
+        assert_eq!(get("現在月"), "4");
+        assert_eq!(get("現在日"), "1");
+        assert_eq!(get("現在曜日"), "1");
+        assert_eq!(get("現在時"), "7");
+        assert_eq!(get("現在分"), "5");
+        assert_eq!(get("現在秒"), "9");
+        assert_eq!(get("現在UNIX時間"), "1711955109");
+        assert_eq!(variable("現在", &clock, 0), None);
+    }
+
+    #[test]
+    fn elapsed_test() {
+        let clock = FakeClock::at(2024, 4, 7, 0, 0, 0).unwrap();
+        let booted = clock.unix_time();
+        clock.advance(Duration::from_secs(2 * 3600 + 3 * 60 + 4));
+        let get = |name| variable(name, &clock, booted).unwrap();
+
+        assert_eq!(get("現在曜日"), "0");
+        assert_eq!(get("起動時"), "2");
+        assert_eq!(get("起動分"), "3");
+        assert_eq!(get("起動秒"), "4");
+        assert_eq!(get("単純起動分"), "123");
+        assert_eq!(get("単純起動秒"), "7384");
+        assert_eq!(get("OS起動時"), "2");
+        assert_eq!(get("単純OS起動秒"), "7384");
+    }
+
+    /// OSの起動時間を読むと失敗する時計
+    struct NoUptime(FakeClock);
+
+    impl Clock for NoUptime {
+        fn now(&self) -> chrono::NaiveDateTime {
+            self.0.now()
+        }
+
+        fn unix_time(&self) -> i64 {
+            self.0.unix_time()
+        }
+
+        fn uptime(&self) -> Duration {
+            panic!("OSの起動時間は読まない")
+        }
+    }
+
+    #[test]
+    fn lazy_test() {
+        let clock = NoUptime(FakeClock::at(2024, 4, 1, 7, 5, 9).unwrap());
+
+        assert_eq!(variable("現在時", &clock, 0).unwrap(), "7");
+        assert_eq!(variable("名前", &clock, 0), None);
+    }
+}
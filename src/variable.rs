@@ -0,0 +1,160 @@
+//! ＄で代入する変数を保持します
+
+use std::{
+    collections::{btree_map, BTreeMap},
+    fmt,
+};
+
+use parser::{token::Primitives, Number};
+
+/// 変数の値
+/// 里々の変数は文字列として保持し、数値として読めれば計算に使えます
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Value(String);
+
+impl Value {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// 数値として読めればその値を返します
+    pub fn as_number(&self) -> Option<Number> {
+        Number::parse(&self.0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Self(s)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Self(s.to_string())
+    }
+}
+
+impl From<Number> for Value {
+    fn from(n: Number) -> Self {
+        Self(n.to_string())
+    }
+}
+
+impl From<&Primitives> for Value {
+    fn from(p: &Primitives) -> Self {
+        match p {
+            Primitives::Number(n) => (*n).into(),
+            Primitives::String(s) => s.as_str().into(),
+        }
+    }
+}
+
+/// 変数名 → 値
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Variables {
+    values: BTreeMap<String, Value>,
+}
+
+impl Variables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+
+    /// 変数を展開します
+    /// 未定義の変数は空文字列になります
+    pub fn expand(&self, name: &str) -> String {
+        self.get(name).map(|v| v.to_string()).unwrap_or_default()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    /// 変数に代入し、以前の値を返します
+    pub fn set(&mut self, name: impl Into<String>, value: impl Into<Value>) -> Option<Value> {
+        self.values.insert(name.into(), value.into())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        self.values.remove(name)
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// 変数を名前順に列挙します
+    pub fn iter(&self) -> btree_map::Iter<'_, String, Value> {
+        self.values.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Variables {
+    type Item = (&'a String, &'a Value);
+    type IntoIter = btree_map::Iter<'a, String, Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value_test() {
+        assert_eq!(Value::from("１２").as_number(), Some(Number::Integer(12)));
+        assert_eq!(Value::from("さくら").as_number(), None);
+        assert_eq!(Value::from(Number::Float(1.5)).as_str(), "1.5");
+        assert_eq!(
+            Value::from(&Primitives::Number(Number::Integer(3))).as_str(),
+            "3"
+        );
+    }
+
+    #[test]
+    fn variables_test() {
+        let mut variables = Variables::new();
+        assert_eq!(variables.expand("未定義"), "");
+
+        assert_eq!(variables.set("回数", "1"), None);
+        assert_eq!(variables.set("回数", "2"), Some(Value::from("1")));
+        variables.set("名前", "さくら");
+        assert_eq!(variables.expand("回数"), "2");
+        assert_eq!(variables.len(), 2);
+        assert_eq!(
+            variables
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["名前", "回数"]
+        );
+
+        assert_eq!(variables.remove("名前"), Some(Value::from("さくら")));
+        assert!(!variables.contains("名前"));
+    }
+}
@@ -0,0 +1,333 @@
+//! ＠単語群の単語を保持します
+//! 実行中に追加・削除した単語は辞書との差分として記録し、savedata.txtに保存します
+//!
+//! savedata.txtには次の形式で書きます
+//! - `＠単語群名<TAB>単語`: 追加した単語
+//! - `－単語群名<TAB>単語`: 削除した辞書の単語
+//! - `－単語群名`: 辞書の単語をすべて削除した
+//!
+//! 単語は変数と同じくそのまま書き、改行（単語群名ではタブも）は取り除きます
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use parser::{ast, token::Content};
+
+use crate::savedata::{one_line, one_line_name};
+
+/// 単語群の単語
+#[derive(Debug, Clone, PartialEq)]
+pub enum Word {
+    Dictionary(Vec<Content>), // 辞書に書かれた単語（展開してから使う）
+    Learned(String),          // 実行中に追加した単語
+}
+
+impl Word {
+    /// 展開の必要がない単語ならその文字列を返します
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            Word::Learned(text) => Some(text),
+            Word::Dictionary(line) => match line.as_slice() {
+                [Content::Sentense(text)] => Some(text),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// ＠ブロック1つ分の単語
+/// 同じ名前の単語群でも、見出しの条件はブロックごとに判定します
+#[derive(Debug, Clone, Default)]
+pub struct WordBlock {
+    pub condition: Option<ast::Expression>, // 見出しの条件（Noneなら常に使う）
+    pub words: Vec<Word>,
+}
+
+/// 1つの単語群への変更
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Changes {
+    pub cleared: bool,             // 辞書の単語をすべて削除したか
+    pub removed: BTreeSet<String>, // 削除した辞書の単語
+    pub added: Vec<String>,        // 追加した単語
+}
+
+/// 単語群名 → 変更
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Learned {
+    groups: BTreeMap<String, Changes>,
+}
+
+impl Learned {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, group: &str) -> Option<&Changes> {
+        self.groups.get(group)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// 単語群名順に列挙します
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Changes)> {
+        self.groups.iter()
+    }
+
+    fn entry(&mut self, group: &str) -> &mut Changes {
+        self.groups.entry(group.to_string()).or_default()
+    }
+}
+
+/// 単語群名 → ＠ブロックごとの単語
+#[derive(Debug, Clone, Default)]
+pub struct Vocabulary {
+    groups: HashMap<String, Vec<WordBlock>>,
+    learned: Learned,
+}
+
+impl Vocabulary {
+    /// 辞書の単語群から作ります
+    /// 同じ名前の単語群は、ブロックを分けたまま1つの名前にまとめます
+    pub fn new(satori: &ast::Satori) -> Self {
+        let mut groups: HashMap<String, Vec<WordBlock>> = HashMap::new();
+        for word_group in &satori.word_group {
+            groups
+                .entry(word_group.label.clone())
+                .or_default()
+                .push(WordBlock {
+                    condition: word_group.condition.clone(),
+                    words: word_group
+                        .contents
+                        .iter()
+                        .map(|line| Word::Dictionary(line.clone()))
+                        .collect(),
+                });
+        }
+        Self {
+            groups,
+            learned: Learned::new(),
+        }
+    }
+
+    pub fn contains(&self, group: &str) -> bool {
+        self.groups.contains_key(group)
+    }
+
+    /// 単語群の＠ブロック（条件を判定する前）
+    pub fn blocks(&self, group: &str) -> &[WordBlock] {
+        self.groups
+            .get(group)
+            .map(|blocks| blocks.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// 条件によらず、単語群のすべての単語を列挙します
+    pub fn words(&self, group: &str) -> impl Iterator<Item = &Word> {
+        self.blocks(group).iter().flat_map(|block| &block.words)
+    }
+
+    pub fn count(&self, group: &str) -> usize {
+        self.blocks(group)
+            .iter()
+            .map(|block| block.words.len())
+            .sum()
+    }
+
+    /// 実行中の変更（保存する差分）
+    pub fn learned(&self) -> &Learned {
+        &self.learned
+    }
+
+    /// 単語を追加します
+    /// 追加した単語は条件のないブロックに入れ、単語群がなければ作ります
+    pub fn add(&mut self, group: &str, word: &str) {
+        let blocks = self.groups.entry(group.to_string()).or_default();
+        let block = match blocks.iter().rposition(|b| b.condition.is_none()) {
+            Some(i) => &mut blocks[i],
+            None => {
+                blocks.push(WordBlock::default());
+                blocks.last_mut().unwrap()
+            }
+        };
+        block.words.push(Word::Learned(word.to_string()));
+        self.learned.entry(group).added.push(word.to_string());
+    }
+
+    /// 単語を削除し、削除した数を返します
+    /// 辞書の単語は展開の必要がないものだけが対象です
+    pub fn remove(&mut self, group: &str, word: &str) -> usize {
+        let Some(blocks) = self.groups.get_mut(group) else {
+            return 0;
+        };
+        let mut removed = 0;
+        let mut dictionary = false;
+        for block in blocks.iter_mut() {
+            let before = block.words.len();
+            block.words.retain(|w| {
+                let matched = w.text() == Some(word);
+                dictionary |= matched && matches!(w, Word::Dictionary(_));
+                !matched
+            });
+            removed += before - block.words.len();
+        }
+
+        let changes = self.learned.entry(group);
+        changes.added.retain(|w| w != word);
+        if dictionary {
+            changes.removed.insert(word.to_string());
+        }
+        removed
+    }
+
+    /// 単語群を空にします
+    /// 単語群がなくても、空にしたことは記録します
+    pub fn clear(&mut self, group: &str) {
+        for block in self.groups.get_mut(group).into_iter().flatten() {
+            block.words.clear();
+        }
+        let changes = self.learned.entry(group);
+        changes.cleared = true;
+        changes.removed.clear();
+        changes.added.clear();
+    }
+
+    /// 保存していた変更を反映します
+    pub fn restore(&mut self, learned: &Learned) {
+        for (group, changes) in learned.iter() {
+            if changes.cleared {
+                self.clear(group);
+            }
+            for word in &changes.removed {
+                self.remove(group, word);
+            }
+            for word in &changes.added {
+                self.add(group, word);
+            }
+        }
+    }
+}
+
+/// savedata.txtの内容から単語群への変更を読み込みます
+/// ＠・－で始まらない行は読み飛ばします
+pub fn parse(src: &str) -> Learned {
+    let mut learned = Learned::new();
+    for line in src.lines() {
+        if let Some(line) = line.strip_prefix('＠') {
+            if let Some((group, word)) = line.split_once('\t') {
+                learned.entry(group).added.push(word.to_string());
+            }
+        } else if let Some(line) = line.strip_prefix('－') {
+            match line.split_once('\t') {
+                Some((group, word)) => {
+                    learned.entry(group).removed.insert(word.to_string());
+                }
+                None => learned.entry(line).cleared = true,
+            }
+        }
+    }
+    learned
+}
+
+/// 単語群への変更をsavedata.txtの形式にします
+pub fn format(learned: &Learned) -> String {
+    let mut text = String::new();
+    for (group, changes) in learned.iter() {
+        let group = one_line_name(group);
+        if changes.cleared {
+            text.push_str(&format!("－{}\r\n", group));
+        }
+        for word in &changes.removed {
+            text.push_str(&format!("－{}\t{}\r\n", group, one_line(word)));
+        }
+        for word in &changes.added {
+            text.push_str(&format!("＠{}\t{}\r\n", group, one_line(word)));
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(vocabulary: &Vocabulary, group: &str) -> Vec<String> {
+        vocabulary
+            .words(group)
+            .map(|word| word.text().unwrap_or("（展開）").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn vocabulary_test() {
+        let satori = parser::block::parse("＠果物\nりんご\nみかん\n（名前）の好物\n", 0).unwrap();
+        let mut vocabulary = Vocabulary::new(&satori);
+        assert_eq!(texts(&vocabulary, "果物"), ["りんご", "みかん", "（展開）"]);
+
+        vocabulary.add("果物", "ぶどう");
+        vocabulary.add("野菜", "にんじん");
+        assert_eq!(vocabulary.remove("果物", "りんご"), 1);
+        assert_eq!(vocabulary.remove("果物", "バナナ"), 0);
+        assert_eq!(texts(&vocabulary, "果物"), ["みかん", "（展開）", "ぶどう"]);
+        assert_eq!(vocabulary.count("野菜"), 1);
+
+        vocabulary.clear("野菜");
+        assert!(vocabulary.contains("野菜"));
+        assert_eq!(vocabulary.count("野菜"), 0);
+        assert!(!vocabulary.contains("肉"));
+    }
+
+    #[test]
+    fn block_test() {
+        let satori = parser::block::parse("＠天気\t（季節）＝＝冬\n雪\n＠天気\n晴れ\n", 0).unwrap();
+        let mut vocabulary = Vocabulary::new(&satori);
+        vocabulary.add("天気", "くもり");
+
+        // 条件つきのブロックは分けたまま、追加した単語は条件のないブロックに入る
+        let blocks = vocabulary.blocks("天気");
+        assert_eq!(blocks.len(), 2);
+        assert!(blocks[0].condition.is_some());
+        assert_eq!(blocks[1].words.len(), 2);
+        assert_eq!(vocabulary.count("天気"), 3);
+    }
+
+    #[test]
+    fn restore_test() {
+        let satori = parser::block::parse("＠果物\nりんご\nみかん\n＠野菜\nなす\n", 0).unwrap();
+        let mut vocabulary = Vocabulary::new(&satori);
+        vocabulary.remove("果物", "りんご");
+        vocabulary.add("果物", "ぶどう");
+        vocabulary.clear("野菜");
+        vocabulary.add("野菜", "にんじん");
+
+        let text = format(vocabulary.learned());
+        assert_eq!(
+            text,
+            "－果物\tりんご\r\n＠果物\tぶどう\r\n－野菜\r\n＠野菜\tにんじん\r\n"
+        );
+        assert_eq!(&parse(&text), vocabulary.learned());
+
+        let mut restored = Vocabulary::new(&satori);
+        restored.restore(&parse(&text));
+        assert_eq!(texts(&restored, "果物"), ["みかん", "ぶどう"]);
+        assert_eq!(texts(&restored, "野菜"), ["にんじん"]);
+        assert_eq!(restored.learned(), vocabulary.learned());
+    }
+
+    #[test]
+    fn one_line_test() {
+        let mut vocabulary = Vocabulary::default();
+        vocabulary.add("果物", "りん\r\n－果物\tご\\n");
+        // 辞書にない単語群を空にしたことも保存する
+        vocabulary.clear("肉");
+
+        // 改行だけを取り除き、さくらスクリプトはそのまま書く
+        let text = format(vocabulary.learned());
+        assert_eq!(text, "＠果物\tりん－果物\tご\\n\r\n－肉\r\n");
+
+        let mut restored = Vocabulary::default();
+        restored.restore(&parse(&text));
+        assert_eq!(texts(&restored, "果物"), ["りん－果物\tご\\n"]);
+        assert!(restored.learned().get("肉").is_some_and(|c| c.cleared));
+    }
+}
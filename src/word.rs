@@ -0,0 +1,169 @@
+//! 単語群（＠）から単語を1つ選びます
+
+use std::collections::HashMap;
+
+use crate::random::Rng;
+
+/// 単語の選び方
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WordPolicy {
+    Random,     // 毎回完全にランダム
+    ShuffleBag, // 全単語を一巡するまで同じ単語を選ばない（里々の既定）
+    Sequential, // 書かれた順に選ぶ
+}
+
+/// 単語群ごとの選択状態
+#[derive(Debug, Clone, Default)]
+struct State {
+    count: usize,        // 前回選んだときの単語数
+    bag: Vec<usize>,     // まだ選んでいない添字（末尾から取り出す）
+    last: Option<usize>, // 直前に選んだ添字
+    next: usize,         // 次に選ぶ添字（Sequential）
+}
+
+/// 単語群ごとの選択状態を保持して単語を選びます
+#[derive(Debug, Clone)]
+pub struct WordPicker {
+    policy: WordPolicy,
+    states: HashMap<String, State>, // 単語群名 → 選択状態
+}
+
+impl WordPicker {
+    pub fn new(policy: WordPolicy) -> Self {
+        Self {
+            policy,
+            states: HashMap::new(),
+        }
+    }
+
+    pub fn policy(&self) -> WordPolicy {
+        self.policy
+    }
+
+    /// 指定した単語群の状態を捨てます
+    /// 単語が追加・削除されたときに使います
+    pub fn reset(&mut self, group: &str) {
+        self.states.remove(group);
+    }
+
+    pub fn clear(&mut self) {
+        self.states.clear();
+    }
+
+    /// count個の単語から1つ選んで添字を返します
+    /// 単語がない場合はNoneを返します
+    pub fn pick(&mut self, group: &str, count: usize, random: &mut dyn Rng) -> Option<usize> {
+        if count == 0 {
+            return None;
+        }
+        let state = self.states.entry(group.to_string()).or_default();
+        if state.count != count {
+            *state = State {
+                count,
+                ..State::default()
+            };
+        }
+
+        let chosen = match self.policy {
+            WordPolicy::Random => random.below(count),
+            WordPolicy::Sequential => {
+                let chosen = state.next % count;
+                state.next = chosen + 1;
+                chosen
+            }
+            WordPolicy::ShuffleBag => {
+                if state.bag.is_empty() {
+                    state.bag = shuffled(count, random);
+                    // 一巡の境目で同じ単語が続かないようにする
+                    if count > 1 && state.bag.last() == state.last.as_ref() {
+                        state.bag.swap(0, count - 1);
+                    }
+                }
+                state.bag.pop()?
+            }
+        };
+        state.last = Some(chosen);
+        Some(chosen)
+    }
+}
+
+impl Default for WordPicker {
+    fn default() -> Self {
+        Self::new(WordPolicy::ShuffleBag)
+    }
+}
+
+/// 0..countを並べ替えて返します（Fisher-Yates）
+fn shuffled(count: usize, random: &mut dyn Rng) -> Vec<usize> {
+    let mut bag = (0..count).collect::<Vec<_>>();
+    for i in (1..count).rev() {
+        bag.swap(i, random.below(i + 1));
+    }
+    bag
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Random;
+
+    #[test]
+    fn shuffle_bag_test() {
+        let mut picker = WordPicker::new(WordPolicy::ShuffleBag);
+        let mut random = Random::new(0);
+
+        let picks = (0..40)
+            .map(|_| picker.pick("天気", 4, &mut random).unwrap())
+            .collect::<Vec<_>>();
+        for round in picks.chunks(4) {
+            let mut round = round.to_vec();
+            round.sort();
+            assert_eq!(round, vec![0, 1, 2, 3]);
+        }
+        assert!(picks.windows(2).all(|w| w[0] != w[1]));
+    }
+
+    #[test]
+    fn two_words_test() {
+        let mut picker = WordPicker::default();
+        let mut random = Random::new(3);
+
+        let picks = (0..20)
+            .map(|_| picker.pick("天気", 2, &mut random).unwrap())
+            .collect::<Vec<_>>();
+        assert!(picks.windows(2).all(|w| w[0] != w[1]));
+    }
+
+    #[test]
+    fn sequential_test() {
+        let mut picker = WordPicker::new(WordPolicy::Sequential);
+        let mut random = Random::new(0);
+
+        let picks = (0..5)
+            .map(|_| picker.pick("天気", 3, &mut random).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(picks, vec![0, 1, 2, 0, 1]);
+        // 単語群ごとに独立している
+        assert_eq!(picker.pick("季節", 3, &mut random), Some(0));
+    }
+
+    #[test]
+    fn random_test() {
+        let mut picker = WordPicker::new(WordPolicy::Random);
+        let mut random = Random::new(0);
+
+        assert!((0..20).all(|_| picker.pick("天気", 3, &mut random).unwrap() < 3));
+        assert_eq!(picker.pick("天気", 0, &mut random), None);
+    }
+
+    #[test]
+    fn count_change_test() {
+        let mut picker = WordPicker::new(WordPolicy::Sequential);
+        let mut random = Random::new(0);
+
+        assert_eq!(picker.pick("天気", 3, &mut random), Some(0));
+        assert_eq!(picker.pick("天気", 3, &mut random), Some(1));
+        // 単語数が変わったら最初から選び直す
+        assert_eq!(picker.pick("天気", 4, &mut random), Some(0));
+    }
+}